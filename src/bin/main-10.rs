@@ -1,17 +1,46 @@
-use std::fs;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs, io,
+};
+
+const SCREEN_WIDTH: usize = 40;
+const SCREEN_HEIGHT: usize = 6;
 
 fn main() {
+    // --assemble <text> or --assemble-image <file>
+    let args = env::args().collect::<Vec<String>>();
+    if args.len() > 2 && (args[1] == "--assemble" || args[1] == "--assemble-image") {
+        let program = if args[1] == "--assemble" {
+            draw_text(&args[2], SCREEN_WIDTH)
+        } else {
+            let image = fs::read_to_string(&args[2]).expect("Should be able to read image");
+            parse_image(&image, SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+        .and_then(|pixels| assemble(&pixels, SCREEN_WIDTH));
+        match program {
+            Ok(program) => print!("{}", program),
+            Err(err) => println!("Could not assemble program: {}", err),
+        }
+        return;
+    }
+
     let file_name = "inputs/10.txt";
     let content = fs::read_to_string(file_name).expect("Should be able to read file");
-    let register_contents = compute_register_contents(&content);
-    let mut signal_strength = 0;
-    for idx in [20, 60, 100, 140, 180, 220] {
-        signal_strength += idx as i64 * register_contents[idx - 1];
+    if args.iter().any(|a| a == "--debug") {
+        let cpu = Cpu::new(InstructionSet::standard());
+        run_debugger(&mut Debugger::new(cpu, &content, SCREEN_WIDTH));
+        return;
+    }
+    let mut cpu = Cpu::new(InstructionSet::standard());
+    let mut signal_strength = SignalStrength::new(&[20, 60, 100, 140, 180, 220]);
+    let mut crt = Crt::new(SCREEN_WIDTH);
+    if let Err(err) = cpu.run(&content, &mut [&mut signal_strength, &mut crt]) {
+        println!("Could not run program: {}", err);
+        return;
     }
-    println!("The total signal strength is {}.", signal_strength);
+    println!("The total signal strength is {}.", signal_strength.total);
 
-    let screen = compute_screen_content(&register_contents);
-    for l in screen.chunks(40) {
+    for l in crt.pixels.chunks(SCREEN_WIDTH) {
         println!(
             "{}",
             l.iter()
@@ -20,45 +49,930 @@ fn main() {
                 .join("")
         );
     }
+    match recognize_letters(&crt.pixels, SCREEN_WIDTH) {
+        Ok(letters) => println!("The CRT shows {}.", letters),
+        Err(err) => println!("Could not read the CRT: {}", err),
+    }
+}
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+// glyphs are separated by a single blank column
+const GLYPH_STRIDE: usize = GLYPH_WIDTH + 1;
+
+const FONT: [(char, [&str; GLYPH_HEIGHT]); 16] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[derive(Debug, PartialEq)]
+enum OcrError {
+    IncompleteScreen(usize),
+    // starting columns of all glyphs that are not part of the font
+    UnknownGlyphs(Vec<usize>),
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OcrError::IncompleteScreen(n_pixels) => {
+                write!(f, "{} pixels do not fill the screen", n_pixels)
+            }
+            OcrError::UnknownGlyphs(columns) => write!(
+                f,
+                "unknown glyphs at columns {}",
+                columns
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+// reads the letters from the first six rows of a screen with the given width,
+// blank glyphs are skipped
+fn recognize_letters(pixels: &[char], width: usize) -> Result<String, OcrError> {
+    if pixels.len() < width * GLYPH_HEIGHT {
+        return Err(OcrError::IncompleteScreen(pixels.len()));
+    }
+    let mut letters = String::new();
+    let mut unknown = Vec::new();
+    for column in (0..=width.saturating_sub(GLYPH_WIDTH)).step_by(GLYPH_STRIDE) {
+        let glyph = (0..GLYPH_HEIGHT)
+            .map(|row| {
+                let start = row * width + column;
+                pixels[start..start + GLYPH_WIDTH]
+                    .iter()
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>();
+        if glyph.iter().all(|row| !row.contains('#')) {
+            continue;
+        }
+        match FONT.iter().find(|(_, rows)| *rows == glyph.as_slice()) {
+            Some((letter, _)) => letters.push(*letter),
+            None => unknown.push(column),
+        }
+    }
+    if unknown.is_empty() {
+        Ok(letters)
+    } else {
+        Err(OcrError::UnknownGlyphs(unknown))
+    }
+}
+
+type Registers = BTreeMap<char, i64>;
+
+type Effect = Box<dyn Fn(&mut Registers, Option<i64>)>;
+
+struct InstructionSpec {
+    cycles: usize,
+    takes_argument: bool,
+    // applied at the end of the last cycle of the instruction
+    effect: Effect,
+}
+
+struct InstructionSet {
+    specs: HashMap<String, InstructionSpec>,
+}
+
+impl InstructionSet {
+    pub fn new() -> Self {
+        Self {
+            specs: HashMap::new(),
+        }
+    }
+
+    // `noop` and `addx` as used by the handheld device
+    pub fn standard() -> Self {
+        let mut instruction_set = Self::new();
+        instruction_set.register("noop", 1, false, |_, _| {});
+        instruction_set.register("addx", 2, true, |registers, arg| {
+            *registers.entry('x').or_insert(0) += arg.unwrap();
+        });
+        instruction_set
+    }
+
+    pub fn register(
+        &mut self,
+        opcode: &str,
+        cycles: usize,
+        takes_argument: bool,
+        effect: impl Fn(&mut Registers, Option<i64>) + 'static,
+    ) {
+        assert!(cycles > 0, "Instructions should take at least one cycle");
+        self.specs.insert(
+            opcode.to_string(),
+            InstructionSpec {
+                cycles,
+                takes_argument,
+                effect: Box::new(effect),
+            },
+        );
+    }
+}
+
+// notified for every cycle, `during` sees the registers while the cycle runs
+// and `after` sees them once an instruction finishing in it took effect
+trait CycleObserver {
+    fn during(&mut self, _cycle: usize, _registers: &Registers) {}
+    fn after(&mut self, _cycle: usize, _registers: &Registers) {}
+}
+
+// sums up cycle times register x during the sampled cycles
+struct SignalStrength {
+    samples: Vec<usize>,
+    total: i64,
+}
+
+impl SignalStrength {
+    pub fn new(samples: &[usize]) -> Self {
+        Self {
+            samples: samples.to_vec(),
+            total: 0,
+        }
+    }
+}
+
+impl CycleObserver for SignalStrength {
+    fn during(&mut self, cycle: usize, registers: &Registers) {
+        if self.samples.contains(&cycle) {
+            self.total += cycle as i64 * registers[&'x'];
+        }
+    }
 }
 
-fn compute_register_contents(content: &str) -> Vec<i64> {
-    let mut register_contents = Vec::new();
-    let mut x = 1;
-    for l in content.lines() {
-        let (instruction, arg) = parse_line(l);
-        match instruction.as_str() {
-            "noop" => register_contents.push(x),
-            "addx" => {
-                register_contents.push(x);
-                register_contents.push(x);
-                x += arg.unwrap();
+// draws one pixel per cycle, lit if the three pixel wide sprite centered on
+// register x covers the pixel being drawn
+struct Crt {
+    width: usize,
+    pixels: Vec<char>,
+}
+
+impl Crt {
+    pub fn new(width: usize) -> Self {
+        Self {
+            width,
+            pixels: Vec::new(),
+        }
+    }
+}
+
+impl CycleObserver for Crt {
+    fn during(&mut self, cycle: usize, registers: &Registers) {
+        let screen_position = ((cycle - 1) % self.width) as i64;
+        if (screen_position - registers[&'x']).abs() <= 1 {
+            self.pixels.push('#');
+        } else {
+            self.pixels.push('.');
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum CpuError {
+    UnknownInstruction(String),
+    MissingArgument(String),
+    UnexpectedArgument(String),
+    InvalidArgument(String),
+    TrailingTokens(String),
+}
+
+#[derive(Debug)]
+struct ExecutionError {
+    line: usize,
+    kind: CpuError,
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            CpuError::UnknownInstruction(opcode) => write!(f, "unknown instruction {}", opcode),
+            CpuError::MissingArgument(opcode) => write!(f, "{} requires an argument", opcode),
+            CpuError::UnexpectedArgument(opcode) => {
+                write!(f, "{} does not take an argument", opcode)
+            }
+            CpuError::InvalidArgument(arg) => write!(f, "invalid argument {}", arg),
+            CpuError::TrailingTokens(tokens) => write!(f, "unexpected trailing {}", tokens),
+        }
+    }
+}
+
+// an instruction that has started but not yet taken effect
+struct Pending {
+    opcode: String,
+    arg: Option<i64>,
+    remaining: usize,
+}
+
+struct Cpu {
+    instruction_set: InstructionSet,
+    registers: Registers,
+    // number of completed cycles
+    cycle: usize,
+    pending: Option<Pending>,
+}
+
+impl Cpu {
+    pub fn new(instruction_set: InstructionSet) -> Self {
+        Self {
+            instruction_set,
+            registers: Registers::from([('x', 1)]),
+            cycle: 0,
+            pending: None,
+        }
+    }
+
+    // starts an instruction, its cycles are run by `tick`
+    pub fn begin(&mut self, opcode: &str, arg: Option<i64>) -> Result<(), CpuError> {
+        assert!(
+            self.pending.is_none(),
+            "The previous instruction should have finished"
+        );
+        let spec = self
+            .instruction_set
+            .specs
+            .get(opcode)
+            .ok_or_else(|| CpuError::UnknownInstruction(opcode.to_string()))?;
+        match (spec.takes_argument, arg) {
+            (true, None) => return Err(CpuError::MissingArgument(opcode.to_string())),
+            (false, Some(_)) => return Err(CpuError::UnexpectedArgument(opcode.to_string())),
+            _ => {}
+        }
+        self.pending = Some(Pending {
+            opcode: opcode.to_string(),
+            arg,
+            remaining: spec.cycles,
+        });
+        Ok(())
+    }
+
+    // runs a single cycle of the pending instruction, returns false if there
+    // is none
+    pub fn tick(&mut self, observers: &mut [&mut dyn CycleObserver]) -> bool {
+        let Some(pending) = self.pending.as_mut() else {
+            return false;
+        };
+        self.cycle += 1;
+        for observer in observers.iter_mut() {
+            observer.during(self.cycle, &self.registers);
+        }
+        pending.remaining -= 1;
+        if pending.remaining == 0 {
+            let pending = self.pending.take().unwrap();
+            (self.instruction_set.specs[&pending.opcode].effect)(&mut self.registers, pending.arg);
+        }
+        for observer in observers.iter_mut() {
+            observer.after(self.cycle, &self.registers);
+        }
+        true
+    }
+
+    pub fn execute(
+        &mut self,
+        opcode: &str,
+        arg: Option<i64>,
+        observers: &mut [&mut dyn CycleObserver],
+    ) -> Result<(), CpuError> {
+        self.begin(opcode, arg)?;
+        while self.tick(observers) {}
+        Ok(())
+    }
+
+    pub fn run(
+        &mut self,
+        content: &str,
+        observers: &mut [&mut dyn CycleObserver],
+    ) -> Result<(), ExecutionError> {
+        for (idx, l) in content.lines().enumerate() {
+            if l.trim().is_empty() {
+                continue;
             }
-            _ => panic!("Unknown instruction"),
+            parse_line(l)
+                .and_then(|(instruction, arg)| self.execute(&instruction, arg, observers))
+                .map_err(|kind| ExecutionError {
+                    line: idx + 1,
+                    kind,
+                })?;
         }
+        Ok(())
     }
-    register_contents
 }
 
-fn parse_line(l: &str) -> (String, Option<i64>) {
+fn parse_line(l: &str) -> Result<(String, Option<i64>), CpuError> {
     let mut l_split = l.split_whitespace();
     let instruction = l_split.next().unwrap().to_string();
-    if let Some(arg) = l_split.next() {
-        (instruction, Some(arg.parse().unwrap()))
+    let arg = l_split.next();
+    let trailing = l_split.collect::<Vec<&str>>();
+    if !trailing.is_empty() {
+        return Err(CpuError::TrailingTokens(trailing.join(" ")));
+    }
+    if let Some(arg) = arg {
+        let arg = arg
+            .parse()
+            .map_err(|_| CpuError::InvalidArgument(arg.to_string()))?;
+        Ok((instruction, Some(arg)))
     } else {
-        (instruction, None)
+        Ok((instruction, None))
     }
 }
 
-fn compute_screen_content(register_contents: &Vec<i64>) -> Vec<char> {
-    let mut screen = Vec::new();
-    for (cycle, position) in register_contents.iter().enumerate() {
-        let screen_position = (cycle % 40) as i64;
-        if (screen_position - position).abs() <= 1 {
-            screen.push('#');
-        } else {
-            screen.push('.');
+#[derive(Debug, PartialEq)]
+enum AssemblyError {
+    UnknownLetter(char),
+    TextTooWide(usize),
+    MalformedImage,
+    // the first pixel that no program can draw correctly
+    Unrepresentable { row: usize, column: usize },
+    VerificationFailed,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssemblyError::UnknownLetter(letter) => write!(f, "no glyph for {}", letter),
+            AssemblyError::TextTooWide(n_letters) => {
+                write!(f, "{} letters do not fit on the screen", n_letters)
+            }
+            AssemblyError::MalformedImage => write!(f, "malformed image"),
+            AssemblyError::Unrepresentable { row, column } => {
+                write!(f, "pixel {} in row {} cannot be drawn", column, row)
+            }
+            AssemblyError::VerificationFailed => write!(f, "program draws a different image"),
         }
     }
-    screen
+}
+
+// draws `text` with the font onto a screen of the given width
+fn draw_text(text: &str, width: usize) -> Result<Vec<char>, AssemblyError> {
+    let n_letters = text.chars().count();
+    if n_letters * GLYPH_STRIDE > width + 1 {
+        return Err(AssemblyError::TextTooWide(n_letters));
+    }
+    let mut pixels = vec!['.'; width * GLYPH_HEIGHT];
+    for (idx, letter) in text.chars().enumerate() {
+        let (_, rows) = FONT
+            .iter()
+            .find(|(l, _)| *l == letter)
+            .ok_or(AssemblyError::UnknownLetter(letter))?;
+        for (row, line) in rows.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                pixels[row * width + idx * GLYPH_STRIDE + col] = c;
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+fn parse_image(content: &str, width: usize, height: usize) -> Result<Vec<char>, AssemblyError> {
+    let rows = content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .collect::<Vec<&str>>();
+    if rows.len() != height
+        || rows
+            .iter()
+            .any(|row| row.len() != width || row.chars().any(|c| c != '#' && c != '.'))
+    {
+        return Err(AssemblyError::MalformedImage);
+    }
+    Ok(rows.iter().flat_map(|row| row.chars()).collect())
+}
+
+// finds a program of `noop` and `addx` instructions that makes the CRT draw
+// `pixels`; register x only matters while it lies within one pixel of the
+// screen, so all other values behave like -2 and the search over cycles and
+// register values stays small
+fn assemble(pixels: &[char], width: usize) -> Result<String, AssemblyError> {
+    let min_x = -2;
+    let max_x = width as i64 + 1;
+    let n_values = (max_x - min_x + 1) as usize;
+    let draws_correctly = |cycle: usize, x: i64| {
+        let screen_position = (cycle % width) as i64;
+        ((screen_position - x).abs() <= 1) == (pixels[cycle] == '#')
+    };
+
+    // for every cycle and register value at the start of an instruction, the
+    // previous instruction's start and its addx argument, if any
+    let mut parents = vec![vec![None; n_values]; pixels.len() + 1];
+    parents[0][(1 - min_x) as usize] = Some((0, 1, None));
+    // no register value reaching this cycle draws its pixel correctly unless
+    // the program can run to completion
+    let mut furthest = 0;
+    for cycle in 0..pixels.len() {
+        for x in min_x..=max_x {
+            if parents[cycle][(x - min_x) as usize].is_none() {
+                continue;
+            }
+            furthest = cycle;
+            if !draws_correctly(cycle, x) {
+                continue;
+            }
+            let noop = &mut parents[cycle + 1][(x - min_x) as usize];
+            if noop.is_none() {
+                *noop = Some((cycle, x, None));
+            }
+            if cycle + 1 < pixels.len() && draws_correctly(cycle + 1, x) {
+                for next_x in min_x..=max_x {
+                    let addx = &mut parents[cycle + 2][(next_x - min_x) as usize];
+                    if addx.is_none() {
+                        *addx = Some((cycle, x, Some(next_x - x)));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut x = (min_x..=max_x)
+        .find(|x| parents[pixels.len()][(x - min_x) as usize].is_some())
+        .ok_or(AssemblyError::Unrepresentable {
+            row: furthest / width,
+            column: furthest % width,
+        })?;
+    let mut instructions = Vec::new();
+    let mut cycle = pixels.len();
+    while cycle > 0 {
+        let (previous_cycle, previous_x, arg) = parents[cycle][(x - min_x) as usize].unwrap();
+        instructions.push(match arg {
+            Some(v) => format!("addx {}\n", v),
+            None => "noop\n".to_string(),
+        });
+        (cycle, x) = (previous_cycle, previous_x);
+    }
+    let program = instructions.into_iter().rev().collect::<String>();
+
+    let mut crt = Crt::new(width);
+    Cpu::new(InstructionSet::standard())
+        .run(&program, &mut [&mut crt])
+        .map_err(|_| AssemblyError::VerificationFailed)?;
+    if crt.pixels != pixels {
+        return Err(AssemblyError::VerificationFailed);
+    }
+    Ok(program)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Breakpoint {
+    Cycle(usize),
+    Register {
+        register: char,
+        comparison: Comparison,
+        value: i64,
+    },
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Cycle(cycle) => write!(f, "cycle {}", cycle),
+            Breakpoint::Register {
+                register,
+                comparison,
+                value,
+            } => {
+                let operator = match comparison {
+                    Comparison::Equal => "==",
+                    Comparison::NotEqual => "!=",
+                    Comparison::Less => "<",
+                    Comparison::LessOrEqual => "<=",
+                    Comparison::Greater => ">",
+                    Comparison::GreaterOrEqual => ">=",
+                };
+                write!(f, "{} {} {}", register, operator, value)
+            }
+        }
+    }
+}
+
+// either a cycle number, e.g., `20`, or a register condition, e.g., `x >= 5`
+fn parse_breakpoint(s: &str) -> Option<Breakpoint> {
+    if let Ok(cycle) = s.trim().parse() {
+        return Some(Breakpoint::Cycle(cycle));
+    }
+    let tokens = s.split_whitespace().collect::<Vec<&str>>();
+    let [register, operator, value] = tokens[..] else {
+        return None;
+    };
+    let mut register_chars = register.chars();
+    let (Some(register), None) = (register_chars.next(), register_chars.next()) else {
+        return None;
+    };
+    let comparison = match operator {
+        "==" => Comparison::Equal,
+        "!=" => Comparison::NotEqual,
+        "<" => Comparison::Less,
+        "<=" => Comparison::LessOrEqual,
+        ">" => Comparison::Greater,
+        ">=" => Comparison::GreaterOrEqual,
+        _ => return None,
+    };
+    Some(Breakpoint::Register {
+        register,
+        comparison,
+        value: value.parse().ok()?,
+    })
+}
+
+// runs a program cycle by cycle; the debugger always stops between cycles,
+// so the registers it shows are the ones seen during the next cycle
+struct Debugger {
+    cpu: Cpu,
+    // line number and text of every instruction, parsed once it starts
+    program: Vec<(usize, String)>,
+    next_instruction: usize,
+    crt: Crt,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Debugger {
+    pub fn new(cpu: Cpu, content: &str, width: usize) -> Self {
+        let program = content
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+            .map(|(idx, l)| (idx + 1, l.trim().to_string()))
+            .collect();
+        Self {
+            cpu,
+            program,
+            next_instruction: 0,
+            crt: Crt::new(width),
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn next_cycle(&self) -> usize {
+        self.cpu.cycle + 1
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cpu.pending.is_none() && self.next_instruction == self.program.len()
+    }
+
+    // the instruction running during the next cycle
+    pub fn current_instruction(&self) -> Option<&(usize, String)> {
+        match self.cpu.pending {
+            Some(_) => self.program.get(self.next_instruction - 1),
+            None => self.program.get(self.next_instruction),
+        }
+    }
+
+    // runs a single cycle, returns false if the program has finished
+    pub fn step(&mut self) -> Result<bool, ExecutionError> {
+        if self.cpu.pending.is_none() {
+            let Some((line, l)) = self.program.get(self.next_instruction) else {
+                return Ok(false);
+            };
+            parse_line(l)
+                .and_then(|(instruction, arg)| self.cpu.begin(&instruction, arg))
+                .map_err(|kind| ExecutionError { line: *line, kind })?;
+            self.next_instruction += 1;
+        }
+        Ok(self.cpu.tick(&mut [&mut self.crt]))
+    }
+
+    // the first breakpoint that holds for the next cycle
+    pub fn hit_breakpoint(&self) -> Option<Breakpoint> {
+        let next_cycle = self.next_cycle();
+        self.breakpoints.iter().copied().find(|b| match *b {
+            Breakpoint::Cycle(cycle) => cycle == next_cycle,
+            Breakpoint::Register {
+                register,
+                comparison,
+                value,
+            } => {
+                let Some(&current) = self.cpu.registers.get(&register) else {
+                    return false;
+                };
+                match comparison {
+                    Comparison::Equal => current == value,
+                    Comparison::NotEqual => current != value,
+                    Comparison::Less => current < value,
+                    Comparison::LessOrEqual => current <= value,
+                    Comparison::Greater => current > value,
+                    Comparison::GreaterOrEqual => current >= value,
+                }
+            }
+        })
+    }
+
+    // runs at least one cycle and then until a breakpoint holds or the
+    // program has finished
+    pub fn resume(&mut self) -> Result<Option<Breakpoint>, ExecutionError> {
+        while self.step()? {
+            if self.is_finished() {
+                break;
+            }
+            if let Some(breakpoint) = self.hit_breakpoint() {
+                return Ok(Some(breakpoint));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn describe(&self) -> String {
+        let registers = self
+            .cpu
+            .registers
+            .iter()
+            .map(|(register, value)| format!("{}={}", register, value))
+            .collect::<Vec<String>>()
+            .join(" ");
+        match self.current_instruction() {
+            _ if self.is_finished() => {
+                format!("finished after cycle {}: {}", self.cpu.cycle, registers)
+            }
+            Some((line, instruction)) => format!(
+                "before cycle {}: {} (line {}: {})",
+                self.next_cycle(),
+                registers,
+                line,
+                instruction
+            ),
+            None => format!("before cycle {}: {}", self.next_cycle(), registers),
+        }
+    }
+}
+
+fn run_debugger(debugger: &mut Debugger) {
+    println!("{}", debugger.describe());
+    for command in io::stdin().lines() {
+        let command = command.expect("Should be able to read command");
+        let (command, arg) = command
+            .trim()
+            .split_once(' ')
+            .unwrap_or((command.trim(), ""));
+        let result = match command {
+            "b" | "break" => {
+                match parse_breakpoint(arg) {
+                    Some(breakpoint) => debugger.breakpoints.push(breakpoint),
+                    None => println!("Unknown breakpoint {}", arg),
+                }
+                Ok(())
+            }
+            "d" | "delete" => {
+                match arg.parse::<usize>() {
+                    Ok(idx) if idx < debugger.breakpoints.len() => {
+                        debugger.breakpoints.remove(idx);
+                    }
+                    _ => println!("No breakpoint {}", arg),
+                }
+                Ok(())
+            }
+            "l" | "list" => {
+                for (idx, breakpoint) in debugger.breakpoints.iter().enumerate() {
+                    println!("{}: {}", idx, breakpoint);
+                }
+                Ok(())
+            }
+            "s" | "step" => {
+                let n_cycles = arg.parse().unwrap_or(1);
+                (0..n_cycles)
+                    .try_for_each(|_| debugger.step().map(|_| ()))
+                    .map(|_| println!("{}", debugger.describe()))
+            }
+            "c" | "continue" => debugger.resume().map(|breakpoint| {
+                if let Some(breakpoint) = breakpoint {
+                    println!("Breakpoint {} hit", breakpoint);
+                }
+                println!("{}", debugger.describe());
+            }),
+            "r" | "registers" => {
+                println!("{}", debugger.describe());
+                Ok(())
+            }
+            "crt" => {
+                for l in debugger.crt.pixels.chunks(debugger.crt.width) {
+                    println!("{}", l.iter().collect::<String>());
+                }
+                Ok(())
+            }
+            "q" | "quit" => return,
+            _ => {
+                println!("Commands: break <cycle|condition>, delete <n>, list, step [n], continue, registers, crt, quit");
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            println!("Could not run program: {}", err);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // records register x during and after every cycle
+    struct Recorder {
+        during: Vec<i64>,
+        after: Vec<i64>,
+    }
+
+    impl CycleObserver for Recorder {
+        fn during(&mut self, _cycle: usize, registers: &Registers) {
+            self.during.push(registers[&'x']);
+        }
+
+        fn after(&mut self, _cycle: usize, registers: &Registers) {
+            self.after.push(registers[&'x']);
+        }
+    }
+
+    #[test]
+    fn test_cpu() {
+        let mut cpu = Cpu::new(InstructionSet::standard());
+        let mut recorder = Recorder {
+            during: Vec::new(),
+            after: Vec::new(),
+        };
+        let mut signal_strength = SignalStrength::new(&[2, 4]);
+        cpu.run(
+            "noop\naddx 3\naddx -5\n",
+            &mut [&mut recorder, &mut signal_strength],
+        )
+        .unwrap();
+        assert_eq!(recorder.during, vec![1, 1, 1, 4, 4]);
+        assert_eq!(recorder.after, vec![1, 1, 4, 4, -1]);
+        assert_eq!(signal_strength.total, 2 + 4 * 4);
+        assert_eq!(cpu.cycle, 5);
+
+        let mut crt = Crt::new(4);
+        Cpu::new(InstructionSet::standard())
+            .run("addx 2\nnoop\naddx -3\nnoop", &mut [&mut crt])
+            .unwrap();
+        assert_eq!(crt.pixels.iter().collect::<String>(), "####.#");
+
+        let err = cpu.run("noop\njmp 4", &mut []).unwrap_err();
+        assert_eq!(err.kind, CpuError::UnknownInstruction("jmp".to_string()));
+        assert_eq!(err.to_string(), "line 2: unknown instruction jmp");
+        assert_eq!(
+            cpu.execute("addx", None, &mut []),
+            Err(CpuError::MissingArgument("addx".to_string()))
+        );
+        assert_eq!(
+            cpu.execute("noop", Some(1), &mut []),
+            Err(CpuError::UnexpectedArgument("noop".to_string()))
+        );
+        let err = cpu.run("noop\naddx foo", &mut []).unwrap_err();
+        assert_eq!(err.kind, CpuError::InvalidArgument("foo".to_string()));
+        assert_eq!(err.to_string(), "line 2: invalid argument foo");
+        let err = cpu.run("addx 1 2\nnoop", &mut []).unwrap_err();
+        assert_eq!(err.kind, CpuError::TrailingTokens("2".to_string()));
+        assert_eq!(err.to_string(), "line 1: unexpected trailing 2");
+        assert_eq!(
+            parse_line("noop x y"),
+            Err(CpuError::TrailingTokens("y".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_recognize_letters() {
+        for text in ["ABCEFGHJ", "KLOPRSUZ", "ZUR"] {
+            assert_eq!(
+                recognize_letters(&draw_text(text, 40).unwrap(), 40),
+                Ok(text.to_string())
+            );
+        }
+        let mut pixels = draw_text("HELLO", 40).unwrap();
+        pixels[5 * 40 + 10] = '.';
+        pixels[3 * 40 + 36] = '#';
+        let err = recognize_letters(&pixels, 40).unwrap_err();
+        assert_eq!(err, OcrError::UnknownGlyphs(vec![10, 35]));
+        assert_eq!(err.to_string(), "unknown glyphs at columns 10, 35");
+        assert_eq!(
+            recognize_letters(&pixels[..200], 40),
+            Err(OcrError::IncompleteScreen(200))
+        );
+    }
+
+    #[test]
+    fn test_assemble() {
+        // register x starts at 1, so the first two pixels are always lit
+        for text in ["BEFPRZ", "ZHKLUGCA", "PLUS", "RAJOC"] {
+            let pixels = draw_text(text, 40).unwrap();
+            let program = assemble(&pixels, 40).unwrap();
+            let mut crt = Crt::new(40);
+            Cpu::new(InstructionSet::standard())
+                .run(&program, &mut [&mut crt])
+                .unwrap();
+            assert_eq!(recognize_letters(&crt.pixels, 40), Ok(text.to_string()));
+        }
+
+        let image = "#.#.\n....\n";
+        let pixels = parse_image(image, 4, 2).unwrap();
+        assert_eq!(parse_image(image, 4, 3), Err(AssemblyError::MalformedImage));
+        assert_eq!(
+            assemble(&pixels, 4),
+            Err(AssemblyError::Unrepresentable { row: 0, column: 1 })
+        );
+        let pixels = parse_image("###.\n.#..\n", 4, 2).unwrap();
+        assert_eq!(
+            assemble(&pixels, 4),
+            Ok("addx 0\naddx 1\naddx -4\naddx 0\n".to_string())
+        );
+
+        assert_eq!(
+            assemble(&draw_text("ABC", 40).unwrap(), 40),
+            Err(AssemblyError::Unrepresentable { row: 0, column: 0 })
+        );
+        assert_eq!(draw_text("ABQ", 40), Err(AssemblyError::UnknownLetter('Q')));
+        assert_eq!(
+            draw_text("ABCEFGHJK", 40),
+            Err(AssemblyError::TextTooWide(9))
+        );
+    }
+
+    #[test]
+    fn test_debugger() {
+        let content = "noop\naddx 3\n\naddx -5\nnoop";
+        let mut debugger = Debugger::new(Cpu::new(InstructionSet::standard()), content, 40);
+        assert_eq!(debugger.describe(), "before cycle 1: x=1 (line 1: noop)");
+        debugger
+            .breakpoints
+            .push(parse_breakpoint("x < 0").unwrap());
+        debugger.breakpoints.push(parse_breakpoint(" 3").unwrap());
+        assert_eq!(debugger.resume().unwrap(), Some(Breakpoint::Cycle(3)));
+        assert_eq!(debugger.describe(), "before cycle 3: x=1 (line 2: addx 3)");
+        assert!(debugger.step().unwrap());
+        assert_eq!(debugger.describe(), "before cycle 4: x=4 (line 4: addx -5)");
+        assert_eq!(
+            debugger.resume().unwrap(),
+            Some(Breakpoint::Register {
+                register: 'x',
+                comparison: Comparison::Less,
+                value: 0
+            })
+        );
+        assert_eq!(debugger.describe(), "before cycle 6: x=-1 (line 5: noop)");
+        assert_eq!(debugger.crt.pixels.iter().collect::<String>(), "#####");
+        assert_eq!(debugger.resume().unwrap(), None);
+        assert_eq!(debugger.describe(), "finished after cycle 6: x=-1");
+        assert!(!debugger.step().unwrap());
+
+        assert_eq!(parse_breakpoint("x <> 3"), None);
+        assert_eq!(parse_breakpoint("xy == 3"), None);
+        assert_eq!(parse_breakpoint("y != -2").unwrap().to_string(), "y != -2");
+
+        let mut debugger = Debugger::new(Cpu::new(InstructionSet::standard()), "noop\njmp 2", 40);
+        assert!(debugger.step().unwrap());
+        assert_eq!(
+            debugger.step().unwrap_err().to_string(),
+            "line 2: unknown instruction jmp"
+        );
+        let mut debugger = Debugger::new(Cpu::new(InstructionSet::standard()), "addx 1x", 40);
+        assert_eq!(
+            debugger.step().unwrap_err().to_string(),
+            "line 1: invalid argument 1x"
+        );
+    }
+
+    #[test]
+    fn test_instruction_set() {
+        let mut instruction_set = InstructionSet::standard();
+        instruction_set.register("addy", 1, true, |registers, arg| {
+            *registers.entry('y').or_insert(0) += arg.unwrap();
+        });
+        instruction_set.register("mulxy", 3, false, |registers, _| {
+            let y = registers.get(&'y').copied().unwrap_or(0);
+            *registers.get_mut(&'x').unwrap() *= y;
+        });
+        let mut cpu = Cpu::new(instruction_set);
+        let mut recorder = Recorder {
+            during: Vec::new(),
+            after: Vec::new(),
+        };
+        cpu.run("addy 3\nmulxy\naddx 2", &mut [&mut recorder])
+            .unwrap();
+        assert_eq!(recorder.during, vec![1, 1, 1, 1, 3, 3]);
+        assert_eq!(cpu.registers, Registers::from([('x', 5), ('y', 3)]));
+    }
 }
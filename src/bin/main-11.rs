@@ -1,117 +1,678 @@
+use num_bigint::BigInt;
 use regex::Regex;
-use std::{fs, ops::DivAssign};
+use std::{cmp::Ordering, collections::HashMap, env, fmt, fs, hash::Hash};
+
+// integer types worry levels can be stored in
+trait Worry: Clone + fmt::Debug + Eq + Hash {
+    fn from_i64(value: i64) -> Self;
+    // `None` if the result does not fit or a division by zero occurs;
+    // division and remainder are Euclidean, i.e., remainders are never
+    // negative
+    fn apply(&self, op: BinaryOp, other: &Self) -> Option<Self>;
+    fn cmp_i64(&self, other: i64) -> Ordering;
+}
+
+impl Worry for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn apply(&self, op: BinaryOp, other: &Self) -> Option<Self> {
+        match op {
+            BinaryOp::Add => self.checked_add(*other),
+            BinaryOp::Sub => self.checked_sub(*other),
+            BinaryOp::Mul => self.checked_mul(*other),
+            BinaryOp::Div => self.checked_div_euclid(*other),
+            BinaryOp::Rem => self.checked_rem_euclid(*other),
+        }
+    }
+
+    fn cmp_i64(&self, other: i64) -> Ordering {
+        self.cmp(&other)
+    }
+}
+
+// never overflows, which allows checking results without any worry relief
+// for a small number of rounds
+impl Worry for BigInt {
+    fn from_i64(value: i64) -> Self {
+        BigInt::from(value)
+    }
+
+    fn apply(&self, op: BinaryOp, other: &Self) -> Option<Self> {
+        match op {
+            BinaryOp::Add => Some(self + other),
+            BinaryOp::Sub => Some(self - other),
+            BinaryOp::Mul => Some(self * other),
+            BinaryOp::Div | BinaryOp::Rem => {
+                if other.cmp_i64(0) == Ordering::Equal {
+                    return None;
+                }
+                let magnitude = BigInt::from(other.magnitude().clone());
+                let remainder = (self % &magnitude + &magnitude) % &magnitude;
+                match op {
+                    BinaryOp::Rem => Some(remainder),
+                    _ => Some((self - remainder) / other),
+                }
+            }
+        }
+    }
+
+    fn cmp_i64(&self, other: i64) -> Ordering {
+        self.cmp(&BigInt::from(other))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Old,
+    Constant(i64),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    // `None` under the same conditions as `Worry::apply`
+    pub fn eval<W: Worry>(&self, old: &W) -> Option<W> {
+        match self {
+            Expr::Old => Some(old.clone()),
+            Expr::Constant(c) => Some(W::from_i64(*c)),
+            Expr::Binary(op, lhs, rhs) => lhs.eval(old)?.apply(*op, &rhs.eval(old)?),
+        }
+    }
+
+    // whether the result modulo m only depends on `old` modulo m, which holds
+    // for sums, differences, and products, and for remainders by constants
+    // that divide m; the constants are added to `moduli`
+    fn collect_moduli(&self, moduli: &mut Vec<i64>) -> bool {
+        match self {
+            Expr::Old | Expr::Constant(_) => true,
+            Expr::Binary(BinaryOp::Div, _, _) => false,
+            Expr::Binary(BinaryOp::Rem, lhs, rhs) => match **rhs {
+                Expr::Constant(c) if c != 0 => {
+                    moduli.push(c.abs());
+                    lhs.collect_moduli(moduli)
+                }
+                _ => false,
+            },
+            Expr::Binary(_, lhs, rhs) => lhs.collect_moduli(moduli) && rhs.collect_moduli(moduli),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ExpressionError {
+    InvalidCharacter(char),
+    UnexpectedToken(String),
+    Incomplete,
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpressionError::InvalidCharacter(c) => write!(f, "invalid character {}", c),
+            ExpressionError::UnexpectedToken(token) => write!(f, "unexpected {}", token),
+            ExpressionError::Incomplete => write!(f, "incomplete expression"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Old,
+    Operator(char),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Old => write!(f, "old"),
+            Token::Operator(op) => write!(f, "{}", op),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' => {
+                let mut number = 0i64;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    number = number
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add(digit as i64))
+                        .ok_or(ExpressionError::InvalidCharacter(c))?;
+                    chars.next();
+                }
+                tokens.push(Token::Number(number));
+            }
+            'a'..='z' => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_lowercase()) {
+                    word.push(c);
+                    chars.next();
+                }
+                if word != "old" {
+                    return Err(ExpressionError::UnexpectedToken(word));
+                }
+                tokens.push(Token::Old);
+            }
+            '+' | '-' | '*' | '/' | '%' => {
+                tokens.push(Token::Operator(c));
+                chars.next();
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                chars.next();
+            }
+            _ => return Err(ExpressionError::InvalidCharacter(c)),
+        }
+    }
+    Ok(tokens)
+}
+
+// recursive descent over
+//   expression = term { ("+" | "-") term }
+//   term       = factor { ("*" | "/" | "%") factor }
+//   factor     = "old" | number | "(" expression ")"
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl ExpressionParser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, ExpressionError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or(ExpressionError::Incomplete)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[(char, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr, ExpressionError>,
+    ) -> Result<Expr, ExpressionError> {
+        let mut expr = operand(self)?;
+        while let Some(&Token::Operator(c)) = self.peek() {
+            let Some(&(_, op)) = operators.iter().find(|(o, _)| *o == c) else {
+                break;
+            };
+            self.position += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(operand(self)?));
+        }
+        Ok(expr)
+    }
+
+    fn expression(&mut self) -> Result<Expr, ExpressionError> {
+        self.binary(&[('+', BinaryOp::Add), ('-', BinaryOp::Sub)], Self::term)
+    }
+
+    fn term(&mut self) -> Result<Expr, ExpressionError> {
+        self.binary(
+            &[
+                ('*', BinaryOp::Mul),
+                ('/', BinaryOp::Div),
+                ('%', BinaryOp::Rem),
+            ],
+            Self::factor,
+        )
+    }
+
+    fn factor(&mut self) -> Result<Expr, ExpressionError> {
+        match self.next()? {
+            Token::Old => Ok(Expr::Old),
+            Token::Number(n) => Ok(Expr::Constant(n)),
+            Token::Open => {
+                let expr = self.expression()?;
+                match self.next()? {
+                    Token::Close => Ok(expr),
+                    token => Err(ExpressionError::UnexpectedToken(token.to_string())),
+                }
+            }
+            token => Err(ExpressionError::UnexpectedToken(token.to_string())),
+        }
+    }
+}
+
+fn parse_expression(s: &str) -> Result<Expr, ExpressionError> {
+    let mut parser = ExpressionParser::new(tokenize(s)?);
+    let expr = parser.expression()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(ExpressionError::UnexpectedToken(token.to_string())),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Condition {
+    DivisibleBy(i64),
+    GreaterThan(i64),
+    LessThan(i64),
+    EqualTo(i64),
+}
+
+impl Condition {
+    pub fn holds<W: Worry>(&self, value: &W) -> bool {
+        match *self {
+            Condition::DivisibleBy(d) => value
+                .apply(BinaryOp::Rem, &W::from_i64(d))
+                .is_some_and(|r| r.cmp_i64(0) == Ordering::Equal),
+            Condition::GreaterThan(n) => value.cmp_i64(n) == Ordering::Greater,
+            Condition::LessThan(n) => value.cmp_i64(n) == Ordering::Less,
+            Condition::EqualTo(n) => value.cmp_i64(n) == Ordering::Equal,
+        }
+    }
+
+    // the modulus a value can be reduced by without changing the outcome, if
+    // there is one
+    fn modulus(&self) -> Option<i64> {
+        match *self {
+            Condition::DivisibleBy(d) => Some(d.abs()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WorryPolicy {
+    DivideBy(i64),
+    // reduces worry levels modulo the least common multiple of all moduli
+    // the monkeys' operations and tests depend on, see `common_modulus`
+    ModuloLcm(i64),
+    None,
+}
+
+impl WorryPolicy {
+    // `divide:<k>`, `lcm`, or `none`
+    pub fn from_name<W: Worry>(name: &str, monkeys: &[Monkey<W>]) -> Result<Self, String> {
+        match name {
+            "lcm" => common_modulus(monkeys)
+                .map(WorryPolicy::ModuloLcm)
+                .ok_or_else(|| "The operations do not allow reducing worry levels.".to_string()),
+            "none" => Ok(WorryPolicy::None),
+            _ => name
+                .strip_prefix("divide:")
+                .and_then(|k| k.parse().ok())
+                .filter(|&k| k != 0)
+                .map(WorryPolicy::DivideBy)
+                .ok_or_else(|| format!("Unknown worry policy {}.", name)),
+        }
+    }
+
+    pub fn relieve<W: Worry>(&self, worry_level: W) -> Option<W> {
+        match *self {
+            WorryPolicy::DivideBy(k) => worry_level.apply(BinaryOp::Div, &W::from_i64(k)),
+            WorryPolicy::ModuloLcm(m) => worry_level.apply(BinaryOp::Rem, &W::from_i64(m)),
+            WorryPolicy::None => Some(worry_level),
+        }
+    }
+}
+
+// items keep their id while they are passed around
+#[derive(Clone, Debug)]
+struct Item<W> {
+    id: usize,
+    worry_level: W,
+}
 
 #[derive(Debug)]
-struct Monkey {
-    queue: Vec<usize>,
-    operator: char,
-    factor: Option<usize>, // None indicates that the previous value is the factor, e.g., old * old
-    divisor: usize,
+struct Monkey<W> {
+    queue: Vec<Item<W>>,
+    operation: Expr,
+    test: Condition,
     targets: [usize; 2],
     n_inspections: usize,
 }
 
-impl Monkey {
+impl<W: Worry> Monkey<W> {
     pub fn new(
-        starting_values: Vec<usize>,
-        operator: char,
-        factor: Option<usize>,
-        divisor: usize,
+        starting_items: Vec<Item<W>>,
+        operation: Expr,
+        test: Condition,
         targets: [usize; 2],
     ) -> Self {
         Self {
-            queue: starting_values,
-            operator,
-            factor,
-            divisor,
+            queue: starting_items,
+            operation,
+            test,
             targets,
             n_inspections: 0,
         }
     }
 
-    pub fn resume(&mut self, reducer: &dyn Fn(usize) -> usize) -> Vec<(usize, usize)> {
-        let mut items_to_send = Vec::new();
-        for value in self.queue.drain(..) {
-            self.n_inspections += 1;
+    // the new worry level and the monkey to throw the item to, `None` if the
+    // worry level does not fit into `W`
+    fn inspect(&self, value: &W, policy: &WorryPolicy) -> Option<(usize, W)> {
+        let worry_level = self.operation.eval(value)?;
+        // keep worry level under control
+        let worry_level = policy.relieve(worry_level)?;
 
-            let factor;
-            if let Some(f) = self.factor {
-                factor = f;
-            } else {
-                factor = value;
-            }
-
-            let worry_level;
-            match self.operator {
-                // '+' => worry_level = (item.value + factor) / 3,
-                // '*' => worry_level = item.value * factor / 3,
-                '+' => worry_level = value + factor,
-                '*' => worry_level = value * factor,
-                _ => panic!("Unexpected operator {}", self.operator),
-            }
-            // keep worry level under control: substract a value divisible by
-            // all divisors
-            // worry_level %= 13 * 17 * 19 * 23;
-            let worry_level = reducer(worry_level);
-
-            let target;
-            if worry_level % self.divisor == 0 {
-                target = self.targets[0];
-            } else {
-                target = self.targets[1];
-            }
+        let target;
+        if self.test.holds(&worry_level) {
+            target = self.targets[0];
+        } else {
+            target = self.targets[1];
+        }
+        Some((target, worry_level))
+    }
 
-            items_to_send.push((target, worry_level));
+    pub fn resume(&mut self, policy: &WorryPolicy) -> Option<Vec<(usize, Item<W>)>> {
+        let mut items_to_send = Vec::new();
+        for item in std::mem::take(&mut self.queue) {
+            self.n_inspections += 1;
+            let (target, worry_level) = self.inspect(&item.worry_level, policy)?;
+            items_to_send.push((
+                target,
+                Item {
+                    id: item.id,
+                    worry_level,
+                },
+            ));
         }
-        items_to_send
+        Some(items_to_send)
     }
 
-    pub fn send(&mut self, item: usize) {
+    pub fn send(&mut self, item: Item<W>) {
         self.queue.push(item);
     }
 }
 
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// a modulus that worry levels can be reduced by without changing where any
+// item goes, if all operations and tests allow it
+fn common_modulus<W>(monkeys: &[Monkey<W>]) -> Option<i64> {
+    let mut moduli = Vec::new();
+    for monkey in monkeys {
+        if !monkey.operation.collect_moduli(&mut moduli) {
+            return None;
+        }
+        moduli.push(monkey.test.modulus()?);
+    }
+    moduli
+        .into_iter()
+        .try_fold(1i64, |m, k| (m / gcd(m, k)).checked_mul(k))
+}
+
 fn main() {
     let filename = "inputs/11.txt";
     let content = fs::read_to_string(filename).expect("Should be able to read file");
 
-    let mut monkeys = parse_monkeys(&content);
-
-    // let reducer = |v| v / 3; // part1
-    let m = monkeys.iter().map(|m| m.divisor).product::<usize>();
-    let reducer = move |v| v % m; // part2
-    for _ in 0..10_000 {
-        run_round(&mut monkeys, &reducer);
+    // [--policy divide:<k>|lcm|none] [--rounds <n>] [--bigint]
+    let args = env::args().collect::<Vec<String>>();
+    let option = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .and_then(|idx| args.get(idx + 1))
+    };
+    let configurations = match (option("--policy"), option("--rounds")) {
+        (None, None) => vec![("divide:3", 20), ("lcm", 10_000)],
+        (policy, n_rounds) => vec![(
+            policy.map_or("lcm", |p| p.as_str()),
+            n_rounds.map_or(10_000, |n| {
+                n.parse().expect("Should be able to parse rounds")
+            }),
+        )],
+    };
+    // --trace <item> or --extrapolate
+    let mode = match option("--trace") {
+        Some(item) => Mode::Trace(item.parse().expect("Should be able to parse item")),
+        None if args.iter().any(|a| a == "--extrapolate") => Mode::Extrapolate,
+        None => Mode::Simulate,
+    };
+    for (policy, n_rounds) in configurations {
+        let result = if args.iter().any(|a| a == "--bigint") {
+            report::<BigInt>(&content, policy, n_rounds, mode)
+        } else {
+            report::<i64>(&content, policy, n_rounds, mode)
+        };
+        if let Err(err) = result {
+            println!("{}", err);
+        }
     }
+}
 
-    let mut n_inspections_per_monkey = monkeys
-        .iter()
-        .map(|m| m.n_inspections)
-        .collect::<Vec<usize>>();
-    n_inspections_per_monkey.sort_unstable();
-    n_inspections_per_monkey.reverse();
+#[derive(Clone, Copy)]
+enum Mode {
+    Simulate,
+    Trace(usize),
+    Extrapolate,
+}
+
+fn report<W: Worry>(
+    content: &str,
+    policy: &str,
+    n_rounds: usize,
+    mode: Mode,
+) -> Result<(), String> {
+    let mut monkeys =
+        parse_monkeys::<W>(content).map_err(|err| format!("Could not parse monkeys: {}", err))?;
+    let policy = WorryPolicy::from_name(policy, &monkeys)?;
+    let overflow = |round| format!("Worry levels overflow in round {}, try --bigint.", round);
+    let n_inspections = match mode {
+        Mode::Extrapolate => {
+            extrapolate_inspections(&monkeys, &policy, n_rounds).map_err(overflow)?
+        }
+        Mode::Simulate => {
+            simulate(&mut monkeys, &policy, n_rounds, None).map_err(overflow)?;
+            monkeys.iter().map(|m| m.n_inspections as u128).collect()
+        }
+        Mode::Trace(item) => {
+            let mut trace = Vec::new();
+            simulate(&mut monkeys, &policy, n_rounds, Some(&mut trace)).map_err(overflow)?;
+            for (round, monkeys) in trajectory(&trace, item).iter().enumerate() {
+                println!(
+                    "Round {}: item {} visits monkeys {:?}.",
+                    round + 1,
+                    item,
+                    monkeys
+                );
+            }
+            monkeys.iter().map(|m| m.n_inspections as u128).collect()
+        }
+    };
     println!(
-        "The level of monkey business is {} after 20 rounds.",
-        n_inspections_per_monkey[0] * n_inspections_per_monkey[1]
+        "The level of monkey business is {} after {} rounds.",
+        monkey_business(&n_inspections),
+        n_rounds
     );
+    Ok(())
+}
+
+// the product of the two highest numbers of inspections
+fn monkey_business(n_inspections: &[u128]) -> u128 {
+    let mut n_inspections_per_monkey = n_inspections.to_vec();
+    n_inspections_per_monkey.sort_unstable();
+    n_inspections_per_monkey.reverse();
+    n_inspections_per_monkey.iter().take(2).product()
+}
+
+// the monkeys that inspect an item within a single round, starting with the
+// one holding it at the beginning, and where it ends up for the next round
+fn follow_item<W: Worry>(
+    monkeys: &[Monkey<W>],
+    policy: &WorryPolicy,
+    monkey: usize,
+    worry_level: &W,
+) -> Option<(Vec<usize>, usize, W)> {
+    let mut inspectors = vec![monkey];
+    let (mut target, mut worry_level) = monkeys[monkey].inspect(worry_level, policy)?;
+    // monkeys take turns in order, so an item thrown forward is inspected
+    // again in the same round
+    while target > *inspectors.last().unwrap() {
+        inspectors.push(target);
+        (target, worry_level) = monkeys[target].inspect(&worry_level, policy)?;
+    }
+    Some((inspectors, target, worry_level))
+}
+
+// inspections per monkey after `n_rounds`, starting with the items currently
+// held; items never affect each other, so every item is followed on its own
+// until it is held by the same monkey with the same worry level at the start
+// of two rounds, from then on the rounds in between repeat; returns the
+// earliest round in which the worry level of any item does not fit into `W`
+// if that happens before
+fn extrapolate_inspections<W: Worry>(
+    monkeys: &[Monkey<W>],
+    policy: &WorryPolicy,
+    n_rounds: usize,
+) -> Result<Vec<u128>, usize> {
+    let mut n_inspections = vec![0u128; monkeys.len()];
+    let mut overflow: Option<usize> = None;
+    let starting_items = monkeys.iter().enumerate().flat_map(|(idx, m)| {
+        m.queue
+            .iter()
+            .map(move |item| (idx, item.worry_level.clone()))
+    });
+    'items: for mut state in starting_items {
+        let mut seen = HashMap::new();
+        let mut rounds: Vec<Vec<usize>> = Vec::new();
+        while rounds.len() < n_rounds {
+            if let Some(&start) = seen.get(&state) {
+                // the rounds from `start` on repeat with this period
+                let period = rounds.len() - start;
+                let remaining = n_rounds - rounds.len();
+                let (n_periods, rest) = (remaining / period, remaining % period);
+                for (offset, inspectors) in rounds[start..].iter().enumerate() {
+                    let repetitions = n_periods as u128 + (offset < rest) as u128;
+                    for &monkey in inspectors {
+                        n_inspections[monkey] += repetitions;
+                    }
+                }
+                break;
+            }
+            let (monkey, worry_level) = &state;
+            let Some((inspectors, target, worry_level)) =
+                follow_item(monkeys, policy, *monkey, worry_level)
+            else {
+                let round = rounds.len() + 1;
+                overflow = Some(overflow.map_or(round, |r| r.min(round)));
+                continue 'items;
+            };
+            seen.insert(state, rounds.len());
+            rounds.push(inspectors);
+            state = (target, worry_level);
+        }
+        for &monkey in rounds.iter().flatten() {
+            n_inspections[monkey] += 1;
+        }
+    }
+    match overflow {
+        Some(round) => Err(round),
+        None => Ok(n_inspections),
+    }
+}
+
+// the monkeys inspecting `item` in every round of a trace
+fn trajectory(trace: &[Vec<(usize, usize)>], item: usize) -> Vec<Vec<usize>> {
+    trace
+        .iter()
+        .map(|inspections| {
+            inspections
+                .iter()
+                .filter(|(_, id)| *id == item)
+                .map(|(monkey, _)| *monkey)
+                .collect()
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+enum MonkeyError {
+    Operation(ExpressionError),
+    Test(String),
+}
+
+#[derive(Debug)]
+struct ParseError {
+    monkey: usize,
+    kind: MonkeyError,
 }
 
-fn parse_monkeys(content: &str) -> Vec<Monkey> {
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "monkey {}: ", self.monkey)?;
+        match &self.kind {
+            MonkeyError::Operation(err) => write!(f, "invalid operation, {}", err),
+            MonkeyError::Test(test) => write!(f, "unknown test {}", test),
+        }
+    }
+}
+
+fn parse_monkeys<W: Worry>(content: &str) -> Result<Vec<Monkey<W>>, ParseError> {
     let mut monkeys = Vec::new();
+    let mut n_items = 0;
     let mut lines = content.lines();
     loop {
         let _monkey_id = lines.next().unwrap();
-        let starting_values = parse_starting_items(lines.next().unwrap());
-        let (operator, factor) = parse_operator_and_factor(lines.next().unwrap());
-        let divisor = parse_divisor(lines.next().unwrap());
+        let error = |kind| ParseError {
+            monkey: monkeys.len(),
+            kind,
+        };
+        let starting_items = parse_starting_items(lines.next().unwrap())
+            .into_iter()
+            .map(|worry_level| {
+                n_items += 1;
+                Item {
+                    id: n_items - 1,
+                    worry_level: W::from_i64(worry_level),
+                }
+            })
+            .collect();
+        let operation =
+            parse_operation(lines.next().unwrap()).map_err(|e| error(MonkeyError::Operation(e)))?;
+        let test = parse_test(lines.next().unwrap()).map_err(|e| error(MonkeyError::Test(e)))?;
         let target0 = parse_target(lines.next().unwrap());
         let target1 = parse_target(lines.next().unwrap());
 
         monkeys.push(Monkey::new(
-            starting_values,
-            operator,
-            factor,
-            divisor,
+            starting_items,
+            operation,
+            test,
             [target0, target1],
         ));
 
@@ -120,10 +681,10 @@ fn parse_monkeys(content: &str) -> Vec<Monkey> {
             break;
         }
     }
-    monkeys
+    Ok(monkeys)
 }
 
-fn parse_starting_items(l: &str) -> Vec<usize> {
+fn parse_starting_items(l: &str) -> Vec<i64> {
     let re = Regex::new(r"Starting items: ([0-9, ]+)").unwrap();
     let caps = re.captures(l);
     if caps.is_none() {
@@ -134,29 +695,30 @@ fn parse_starting_items(l: &str) -> Vec<usize> {
     items
         .split_whitespace()
         .map(|v| v.parse().unwrap())
-        .collect::<Vec<usize>>()
+        .collect::<Vec<i64>>()
 }
 
-fn parse_operator_and_factor(l: &str) -> (char, Option<usize>) {
-    let re = Regex::new(r"Operation: new = old ([+*]) ([0-9]+|old)").unwrap();
-    let caps = re.captures(l).unwrap();
-    let op = caps.get(1).unwrap().as_str().chars().next().unwrap();
-    if let Ok(multiplier) = caps.get(2).unwrap().as_str().parse() {
-        (op, Some(multiplier))
-    } else {
-        (op, None)
-    }
+fn parse_operation(l: &str) -> Result<Expr, ExpressionError> {
+    let expression = l
+        .trim()
+        .strip_prefix("Operation: new =")
+        .ok_or_else(|| ExpressionError::UnexpectedToken(l.trim().to_string()))?;
+    parse_expression(expression)
 }
 
-fn parse_divisor(l: &str) -> usize {
-    let re = Regex::new(r"Test: divisible by ([0-9]+)").unwrap();
-    re.captures(l)
-        .unwrap()
-        .get(1)
-        .unwrap()
-        .as_str()
-        .parse()
-        .unwrap()
+fn parse_test(l: &str) -> Result<Condition, String> {
+    let re =
+        Regex::new(r"Test: (divisible by|greater than|less than|equal to) (-?[0-9]+)$").unwrap();
+    let test = l.trim();
+    let caps = re.captures(test).ok_or_else(|| test.to_string())?;
+    let n = caps[2].parse().map_err(|_| test.to_string())?;
+    match &caps[1] {
+        "divisible by" if n != 0 => Ok(Condition::DivisibleBy(n)),
+        "greater than" => Ok(Condition::GreaterThan(n)),
+        "less than" => Ok(Condition::LessThan(n)),
+        "equal to" => Ok(Condition::EqualTo(n)),
+        _ => Err(test.to_string()),
+    }
 }
 
 fn parse_target(l: &str) -> usize {
@@ -170,13 +732,283 @@ fn parse_target(l: &str) -> usize {
         .unwrap()
 }
 
-fn run_round(monkeys: &mut Vec<Monkey>, reducer: &dyn Fn(usize) -> usize) {
+// returns the monkey and item id of every inspection in order
+fn run_round<W: Worry>(
+    monkeys: &mut Vec<Monkey<W>>,
+    policy: &WorryPolicy,
+) -> Option<Vec<(usize, usize)>> {
+    let mut inspections = Vec::new();
     for i in 0..monkeys.len() {
         let m = &mut monkeys[i];
-        let items_to_send = m.resume(reducer);
+        let items_to_send = m.resume(policy)?;
         for item in items_to_send {
             let (target, item) = item;
+            inspections.push((i, item.id));
             monkeys[target].send(item);
         }
     }
+    Some(inspections)
+}
+
+// returns the first round in which a worry level does not fit into `W`; the
+// inspections of every round are appended to `trace` if given
+fn simulate<W: Worry>(
+    monkeys: &mut Vec<Monkey<W>>,
+    policy: &WorryPolicy,
+    n_rounds: usize,
+    mut trace: Option<&mut Vec<Vec<(usize, usize)>>>,
+) -> Result<(), usize> {
+    for round in 1..=n_rounds {
+        let inspections = run_round(monkeys, policy).ok_or(round)?;
+        if let Some(trace) = trace.as_mut() {
+            trace.push(inspections);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONKEYS: &str = "Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old * 19
+  Test: divisible by 23
+    If true: throw to monkey 2
+    If false: throw to monkey 3
+
+Monkey 1:
+  Starting items: 54, 65, 75, 74
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 79, 60, 97
+  Operation: new = old * old
+  Test: divisible by 13
+    If true: throw to monkey 1
+    If false: throw to monkey 3
+
+Monkey 3:
+  Starting items: 74
+  Operation: new = old + 3
+  Test: divisible by 17
+    If true: throw to monkey 0
+    If false: throw to monkey 1
+";
+
+    fn run<W: Worry>(policy: &str, n_rounds: usize) -> Result<Vec<Monkey<W>>, usize> {
+        let mut monkeys = parse_monkeys::<W>(MONKEYS).unwrap();
+        let policy = WorryPolicy::from_name(policy, &monkeys).unwrap();
+        simulate(&mut monkeys, &policy, n_rounds, None)?;
+        Ok(monkeys)
+    }
+
+    fn n_inspections<W>(monkeys: &[Monkey<W>]) -> Vec<u128> {
+        monkeys.iter().map(|m| m.n_inspections as u128).collect()
+    }
+
+    #[test]
+    fn test_monkey_business() {
+        let business = |monkeys: Vec<Monkey<i64>>| monkey_business(&n_inspections(&monkeys));
+        assert_eq!(business(run("divide:3", 20).unwrap()), 10605);
+        assert_eq!(
+            monkey_business(&n_inspections(&run::<BigInt>("divide:3", 20).unwrap())),
+            10605
+        );
+        assert_eq!(business(run("lcm", 10_000).unwrap()), 2713310158);
+        let monkeys = parse_monkeys::<i64>(MONKEYS).unwrap();
+        assert_eq!(
+            WorryPolicy::from_name("lcm", &monkeys),
+            Ok(WorryPolicy::ModuloLcm(23 * 19 * 13 * 17))
+        );
+        assert!(WorryPolicy::from_name("divide:0", &monkeys).is_err());
+    }
+
+    #[test]
+    fn test_unreduced_worry_levels() {
+        assert_eq!(run::<i64>("none", 20).unwrap_err(), 11);
+        let reduced = run::<i64>("lcm", 10).unwrap();
+        let unreduced = run::<BigInt>("none", 10).unwrap();
+        for (r, u) in reduced.iter().zip(unreduced.iter()) {
+            assert_eq!(r.n_inspections, u.n_inspections);
+            let u = u
+                .queue
+                .iter()
+                .map(|item| {
+                    (
+                        item.id,
+                        item.worry_level
+                            .apply(BinaryOp::Rem, &BigInt::from(96577))
+                            .unwrap(),
+                    )
+                })
+                .collect::<Vec<(usize, BigInt)>>();
+            assert_eq!(
+                r.queue
+                    .iter()
+                    .map(|item| (item.id, BigInt::from(item.worry_level)))
+                    .collect::<Vec<(usize, BigInt)>>(),
+                u
+            );
+        }
+        assert_eq!(
+            BigInt::from(-7).apply(BinaryOp::Div, &BigInt::from(2)),
+            Some(BigInt::from(-4))
+        );
+        assert_eq!(
+            BigInt::from(-7).apply(BinaryOp::Rem, &BigInt::from(-2)),
+            Some(BigInt::from(1))
+        );
+        assert_eq!(
+            (-7i64).apply(BinaryOp::Div, &-2),
+            BigInt::from(-7)
+                .apply(BinaryOp::Div, &BigInt::from(-2))
+                .map(|w| i64::try_from(w).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_trajectory() {
+        let mut monkeys = parse_monkeys::<i64>(MONKEYS).unwrap();
+        assert_eq!(monkeys[1].queue[0].id, 2);
+        let policy = WorryPolicy::DivideBy(3);
+        let mut trace = Vec::new();
+        simulate(&mut monkeys, &policy, 2, Some(&mut trace)).unwrap();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(
+            trace[0].iter().filter(|(monkey, _)| *monkey == 0).count(),
+            2
+        );
+        // 79 becomes 500 at monkey 0 and goes to monkey 3, which makes it 167
+        // and throws it to monkey 1 for the next round
+        assert_eq!(trajectory(&trace, 0), vec![vec![0, 3], vec![1, 2, 3]]);
+        let (inspectors, target, worry_level) =
+            follow_item(&parse_monkeys(MONKEYS).unwrap(), &policy, 0, &79i64).unwrap();
+        assert_eq!((inspectors, target, worry_level), (vec![0, 3], 1, 167));
+    }
+
+    #[test]
+    fn test_extrapolation() {
+        for (policy, n_rounds) in [("divide:3", 20), ("lcm", 1), ("lcm", 1000), ("lcm", 10_000)] {
+            let monkeys = parse_monkeys::<i64>(MONKEYS).unwrap();
+            let policy = WorryPolicy::from_name(policy, &monkeys).unwrap();
+            let extrapolated = extrapolate_inspections(&monkeys, &policy, n_rounds).unwrap();
+            let mut simulated = parse_monkeys::<i64>(MONKEYS).unwrap();
+            simulate(&mut simulated, &policy, n_rounds, None).unwrap();
+            assert_eq!(extrapolated, n_inspections(&simulated));
+        }
+        let monkeys = parse_monkeys::<i64>(MONKEYS).unwrap();
+        let policy = WorryPolicy::from_name("lcm", &monkeys).unwrap();
+        // once their trajectories repeat, items visit monkeys at a constant
+        // rate, so the counts grow with the number of rounds
+        let short = extrapolate_inspections(&monkeys, &policy, 10_000).unwrap();
+        let long = extrapolate_inspections(&monkeys, &policy, 1_000_000_000_000).unwrap();
+        for (s, l) in short.iter().zip(long.iter()) {
+            assert!(l.abs_diff(s * 100_000_000) * 100 < *l);
+        }
+        assert_eq!(
+            extrapolate_inspections(&monkeys, &WorryPolicy::None, 20),
+            Err(11)
+        );
+
+        // the first item only overflows in round 6, the second one already
+        // in round 1
+        let content = "Monkey 0:
+  Starting items: 2, 4000000000
+  Operation: new = old * old
+  Test: divisible by 2
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 
+  Operation: new = old
+  Test: divisible by 2
+    If true: throw to monkey 0
+    If false: throw to monkey 0";
+        let monkeys = parse_monkeys::<i64>(content).unwrap();
+        assert_eq!(
+            extrapolate_inspections(&monkeys, &WorryPolicy::None, 20),
+            Err(1)
+        );
+        let mut simulated = parse_monkeys::<i64>(content).unwrap();
+        assert_eq!(
+            simulate(&mut simulated, &WorryPolicy::None, 20, None),
+            Err(1)
+        );
+    }
+
+    #[test]
+    fn test_expression() {
+        let expr = parse_expression("(old + 3) * old - old % 4 / 2").unwrap();
+        assert_eq!(expr.eval(&5), Some(40));
+        assert_eq!(expr.eval(&7), Some(70 - 1));
+        assert_eq!(parse_expression("2 - 3 - 4").unwrap().eval(&0), Some(-5));
+        assert_eq!(
+            parse_expression("old / (old - old)").unwrap().eval(&3),
+            None
+        );
+        assert_eq!(
+            parse_expression("old * old").unwrap().eval(&(1i64 << 32)),
+            None
+        );
+        assert_eq!(
+            parse_expression("(old - 10) % 7").unwrap().eval(&1),
+            Some(5)
+        );
+
+        assert_eq!(
+            parse_expression("old ^ 2"),
+            Err(ExpressionError::InvalidCharacter('^'))
+        );
+        assert_eq!(
+            parse_expression("(old + 1"),
+            Err(ExpressionError::Incomplete)
+        );
+        assert_eq!(
+            parse_expression("old 1"),
+            Err(ExpressionError::UnexpectedToken("1".to_string()))
+        );
+        assert_eq!(
+            parse_expression("new + 1"),
+            Err(ExpressionError::UnexpectedToken("new".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_common_modulus() {
+        let with_operation = |operation: &str, test: &str| {
+            let content =
+                MONKEYS
+                    .replacen("old * 19", operation, 1)
+                    .replacen("divisible by 23", test, 1);
+            parse_monkeys::<i64>(&content).map(|monkeys| common_modulus(&monkeys))
+        };
+        assert_eq!(
+            with_operation("(old - 2) * old % 10", "divisible by 26").unwrap(),
+            Some(10 * 13 * 19 * 17)
+        );
+        assert_eq!(with_operation("old / 2", "divisible by 23").unwrap(), None);
+        assert_eq!(
+            with_operation("old % old", "divisible by 23").unwrap(),
+            None
+        );
+        assert_eq!(
+            with_operation("old * 19", "greater than 100").unwrap(),
+            None
+        );
+
+        let monkeys =
+            parse_monkeys::<i64>(&MONKEYS.replacen("divisible by 23", "less than 100", 1)).unwrap();
+        assert_eq!(monkeys[0].test, Condition::LessThan(100));
+        assert!(monkeys[0].test.holds(&-3i64));
+        let err = with_operation("old * 19", "odd").unwrap_err();
+        assert_eq!(err.to_string(), "monkey 0: unknown test Test: odd");
+        let err = with_operation("old ** 2", "divisible by 23").unwrap_err();
+        assert_eq!(err.to_string(), "monkey 0: invalid operation, unexpected *");
+    }
 }
@@ -1,8 +1,21 @@
-use std::{collections::HashSet, fs, str::Chars};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fs,
+};
 
 fn main() {
     let file_path = "inputs/3.txt";
     let contents = fs::read_to_string(file_path).expect("Should have been able to read the file");
+    if let Some(format) = env::args().nth(1) {
+        let report = build_reorganization_report(&contents);
+        match format.as_str() {
+            "--table" => print!("{}", report.to_table()),
+            "--json" => println!("{}", report.to_json()),
+            _ => panic!("Unknown report format {}", format),
+        }
+        return;
+    }
     let priorities = compute_priorities_of_duplicated_items(&contents);
     println!(
         "The sum of all (shared item) priorities is {}.",
@@ -24,14 +37,9 @@ fn compute_priorities_of_duplicated_items(list: &str) -> Vec<u32> {
     priorities
 }
 
+// the shared item with the lowest priority if there are several
 fn find_shared_item(line: &str) -> Option<char> {
-    let n_2 = line.len() / 2;
-    let first_half: HashSet<char> = line[..n_2].chars().collect();
-    let second_half: HashSet<char> = line[n_2..].chars().collect();
-    if let Some(shared_item) = first_half.intersection(&second_half).next() {
-        return Some(*shared_item);
-    }
-    None
+    find_all_shared_items(line).first().copied()
 }
 
 fn char_to_value(c: char) -> u32 {
@@ -71,3 +79,297 @@ fn determine_badge(group0: &str, group1: &str, group2: &str) -> char {
         .next()
         .unwrap())
 }
+
+#[derive(Serialize)]
+struct RucksackEntry {
+    rucksack: usize,
+    shared_items: Vec<char>,
+    // sum over all shared items, zero for a correctly packed rucksack
+    priority: u32,
+}
+
+// exchanges one item of the first compartment with one of the second
+#[derive(Serialize)]
+struct Swap {
+    rucksack: usize,
+    first: char,
+    second: char,
+}
+
+#[derive(Serialize)]
+struct ReorganizationReport {
+    rucksacks: Vec<RucksackEntry>,
+    // copies of shared items that the swaps move to the other compartment
+    misplaced_copies: BTreeMap<char, usize>,
+    swaps: Vec<Swap>,
+    // rucksacks whose item counts cannot be split into two equally sized
+    // compartments without sharing an item type
+    unfixable: Vec<usize>,
+}
+
+impl ReorganizationReport {
+    fn to_table(&self) -> String {
+        let mut s = String::new();
+        s.push_str("rucksack | shared items | priority\n");
+        for entry in self.rucksacks.iter() {
+            s.push_str(&format!(
+                "{:>8} | {:>12} | {:>8}\n",
+                entry.rucksack,
+                entry.shared_items.iter().collect::<String>(),
+                entry.priority
+            ));
+        }
+        s.push_str("\nitem | priority | misplaced copies\n");
+        for (item, count) in self.misplaced_copies.iter() {
+            s.push_str(&format!(
+                "{:>4} | {:>8} | {:>16}\n",
+                item,
+                char_to_value(*item),
+                count
+            ));
+        }
+        s.push_str("\nswaps\n");
+        for swap in self.swaps.iter() {
+            s.push_str(&format!(
+                "rucksack {}: swap '{}' from the first with '{}' from the second compartment\n",
+                swap.rucksack, swap.first, swap.second
+            ));
+        }
+        for rucksack in self.unfixable.iter() {
+            s.push_str(&format!(
+                "rucksack {}: cannot be fixed by swaps\n",
+                rucksack
+            ));
+        }
+        s
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Should be able to serialize report")
+    }
+}
+
+fn build_reorganization_report(list: &str) -> ReorganizationReport {
+    let mut rucksacks = Vec::new();
+    let mut misplaced_copies = BTreeMap::new();
+    let mut swaps = Vec::new();
+    let mut unfixable = Vec::new();
+    for (i, l) in list.lines().enumerate() {
+        let shared_items = find_all_shared_items(l);
+        let priority = shared_items.iter().map(|c| char_to_value(*c)).sum();
+        let (first, second) = l.split_at(l.len() / 2);
+        // prefer moving the shared items into the second compartment, but
+        // try the other direction if no partners add up
+        let pairs = match plan_swaps(first, second, &shared_items) {
+            Some(pairs) => Some(pairs),
+            None => plan_swaps(second, first, &shared_items)
+                .map(|pairs| pairs.into_iter().map(|(a, b)| (b, a)).collect()),
+        };
+        match pairs {
+            Some(pairs) => {
+                for (a, b) in pairs {
+                    let shared = if shared_items.contains(&a) { a } else { b };
+                    *misplaced_copies.entry(shared).or_insert(0) += 1;
+                    swaps.push(Swap {
+                        rucksack: i,
+                        first: a,
+                        second: b,
+                    });
+                }
+            }
+            None => unfixable.push(i),
+        }
+        rucksacks.push(RucksackEntry {
+            rucksack: i,
+            shared_items,
+            priority,
+        });
+    }
+    ReorganizationReport {
+        rucksacks,
+        misplaced_copies,
+        swaps,
+        unfixable,
+    }
+}
+
+// pairs every copy of the shared items in `from` with an item of `to` that
+// does not occur in `from`; partner item types are moved as a whole so they
+// do not end up in both compartments, which makes this a subset sum over
+// their counts
+fn plan_swaps(from: &str, to: &str, shared_items: &[char]) -> Option<Vec<(char, char)>> {
+    let misplaced = from
+        .chars()
+        .filter(|c| shared_items.contains(c))
+        .collect::<Vec<char>>();
+    let mut candidates = BTreeMap::new();
+    for c in to.chars().filter(|c| !from.contains(*c)) {
+        *candidates.entry(c).or_insert(0) += 1;
+    }
+    let candidates = candidates.into_iter().collect::<Vec<(char, usize)>>();
+    // reachable[k][sum] tells whether the first k candidates can add up to sum
+    let target = misplaced.len();
+    let mut reachable = vec![vec![false; target + 1]; candidates.len() + 1];
+    reachable[0][0] = true;
+    for (k, (_, count)) in candidates.iter().enumerate() {
+        for sum in 0..=target {
+            reachable[k + 1][sum] =
+                reachable[k][sum] || (sum >= *count && reachable[k][sum - count]);
+        }
+    }
+    if !reachable[candidates.len()][target] {
+        return None;
+    }
+    let mut partners = Vec::new();
+    let mut sum = target;
+    for k in (0..candidates.len()).rev() {
+        if !reachable[k][sum] {
+            let (item, count) = candidates[k];
+            partners.extend(std::iter::repeat_n(item, count));
+            sum -= count;
+        }
+    }
+    partners.reverse();
+    Some(misplaced.into_iter().zip(partners).collect())
+}
+
+fn find_all_shared_items(line: &str) -> Vec<char> {
+    let n_2 = line.len() / 2;
+    let first_half: HashSet<char> = line[..n_2].chars().collect();
+    let second_half: HashSet<char> = line[n_2..].chars().collect();
+    let mut shared_items = first_half
+        .intersection(&second_half)
+        .cloned()
+        .collect::<Vec<char>>();
+    shared_items.sort_unstable_by_key(|c| char_to_value(*c));
+    shared_items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "vJrwpWtwJgWrhcsFMMfFFhFp
+jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL
+PmmdzqPrVvPwwTWBwg
+wMqvLMZHhHMvwLHjbvcjnnSBnvTQFn
+ttgJtRGJQctTZtZT
+CrZsJsPPZsGzwwsLwLmpwMDw";
+
+    // applies the swaps and returns the rucksacks as they end up
+    fn apply_swaps(list: &str, swaps: &[Swap]) -> Vec<String> {
+        list.lines()
+            .enumerate()
+            .map(|(i, l)| {
+                let (first, second) = l.split_at(l.len() / 2);
+                let mut first = first.chars().collect::<Vec<char>>();
+                let mut second = second.chars().collect::<Vec<char>>();
+                for swap in swaps.iter().filter(|s| s.rucksack == i) {
+                    let a = first.iter().position(|c| *c == swap.first).unwrap();
+                    let b = second.iter().position(|c| *c == swap.second).unwrap();
+                    first[a] = swap.second;
+                    second[b] = swap.first;
+                }
+                first.into_iter().chain(second).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_build_reorganization_report() {
+        let report = build_reorganization_report(SAMPLE);
+        assert_eq!(
+            report
+                .rucksacks
+                .iter()
+                .map(|e| (e.rucksack, e.shared_items.clone(), e.priority))
+                .collect::<Vec<(usize, Vec<char>, u32)>>(),
+            vec![
+                (0, vec!['p'], 16),
+                (1, vec!['L'], 38),
+                (2, vec!['P'], 42),
+                (3, vec!['v'], 22),
+                (4, vec!['t'], 20),
+                (5, vec!['s'], 19),
+            ]
+        );
+        assert_eq!(
+            report.misplaced_copies,
+            BTreeMap::from([('L', 2), ('P', 2), ('p', 1), ('s', 3), ('t', 3), ('v', 2)])
+        );
+        assert_eq!(
+            report
+                .swaps
+                .iter()
+                .map(|s| (s.rucksack, s.first, s.second))
+                .collect::<Vec<(usize, char, char)>>(),
+            vec![
+                (0, 'p', 'c'),
+                (1, 'L', 'S'),
+                (1, 'L', 'S'),
+                (2, 'P', 'B'),
+                (2, 'P', 'T'),
+                (3, 'v', 'B'),
+                (3, 'v', 'F'),
+                (4, 't', 'Q'),
+                (4, 't', 'T'),
+                (4, 't', 'T'),
+                (5, 's', 'D'),
+                (5, 's', 'L'),
+                (5, 's', 'L'),
+            ]
+        );
+        assert!(report.unfixable.is_empty());
+        for (i, l) in apply_swaps(SAMPLE, &report.swaps).iter().enumerate() {
+            assert_eq!(l.len(), SAMPLE.lines().nth(i).unwrap().len());
+            assert_eq!(find_shared_item(l), None, "rucksack {} still shares", i);
+        }
+    }
+
+    #[test]
+    fn test_clean_and_multiply_shared_rucksacks() {
+        // the second rucksack is already packed correctly and the third one
+        // shares both a and b
+        let report = build_reorganization_report("vJrwpWtwJgWrhcsFMMfFFhFp\nabcdef\nabxbay");
+        assert_eq!(
+            report
+                .rucksacks
+                .iter()
+                .map(|e| (e.shared_items.clone(), e.priority))
+                .collect::<Vec<(Vec<char>, u32)>>(),
+            vec![(vec!['p'], 16), (vec![], 0), (vec!['a', 'b'], 3)]
+        );
+        assert_eq!(find_shared_item("abxbay"), Some('a'));
+        assert!(report.swaps.iter().all(|s| s.rucksack != 1));
+        assert_eq!(report.unfixable, vec![2]);
+        assert!(report
+            .to_table()
+            .contains("       1 |              |        0"));
+
+        // the report still works on rucksacks fixed by its own swaps
+        let fixed = apply_swaps(SAMPLE, &build_reorganization_report(SAMPLE).swaps).join("\n");
+        let report = build_reorganization_report(&fixed);
+        assert!(report.rucksacks.iter().all(|e| e.priority == 0));
+        assert!(report.swaps.is_empty());
+    }
+
+    #[test]
+    fn test_unfixable_rucksack() {
+        // three copies of one item cannot be split into halves of two
+        let report = build_reorganization_report("aaab");
+        assert!(report.swaps.is_empty());
+        assert_eq!(report.unfixable, vec![0]);
+    }
+
+    #[test]
+    fn test_report_to_json() {
+        let report = build_reorganization_report(SAMPLE);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["rucksacks"].as_array().unwrap().len(), 6);
+        assert_eq!(json["rucksacks"][1]["shared_items"][0], "L");
+        assert_eq!(json["misplaced_copies"]["P"], 2);
+        assert_eq!(json["swaps"].as_array().unwrap().len(), 13);
+        assert_eq!(json["swaps"][0]["first"], "p");
+        assert_eq!(json["swaps"][0]["second"], "c");
+    }
+}
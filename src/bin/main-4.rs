@@ -10,14 +10,254 @@ fn main() {
     );
     let count = count_pairs_with_overlap(&contents, false);
     println!("There are {} pairs with overlap.", count);
+
+    let pairs = parse_pairs(&contents);
+    let intervals = pairs
+        .iter()
+        .flat_map(|(range0, range1)| [*range0, *range1])
+        .collect::<Vec<Interval<u64>>>();
+    let assigned = IntervalSet::from_intervals(&intervals);
+    println!(
+        "The elves are assigned to {} distinct sections.",
+        assigned.len()
+    );
+    if let Some(hull) = assigned.hull() {
+        println!(
+            "{} sections between the first and last assigned one are not cleaned by anyone.",
+            assigned.complement_within(&hull).len()
+        );
+    }
+    let shared_within_pairs = pairs
+        .iter()
+        .filter_map(|(range0, range1)| range0.intersection(range1))
+        .collect::<Vec<Interval<u64>>>();
+    println!(
+        "{} sections are cleaned by both elves of a pair.",
+        IntervalSet::from_intervals(&shared_within_pairs).len()
+    );
+    println!(
+        "{} sections are cleaned by more than one elf.",
+        covered_by_more_than(&intervals, 1).len()
+    );
+    println!(
+        "At most {} elves clean the same section.",
+        max_simultaneous_overlap(&intervals)
+    );
+}
+
+trait Discrete: Copy + Ord {
+    fn succ(self) -> Option<Self>;
+    fn pred(self) -> Option<Self>;
+    fn distance(self, other: Self) -> u128;
+    fn max_value() -> Self;
+}
+
+macro_rules! impl_discrete {
+    ($($t:ty),*) => {
+        $(
+            impl Discrete for $t {
+                fn succ(self) -> Option<Self> {
+                    self.checked_add(1)
+                }
+
+                fn pred(self) -> Option<Self> {
+                    self.checked_sub(1)
+                }
+
+                fn distance(self, other: Self) -> u128 {
+                    (other as i128 - self as i128).unsigned_abs()
+                }
+
+                fn max_value() -> Self {
+                    <$t>::MAX
+                }
+            }
+        )*
+    };
+}
+
+impl_discrete!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+// inclusive on both ends, i.e., `Interval::new(2, 4)` contains 2, 3, and 4
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Interval<T> {
+    start: T,
+    end: T,
+}
+
+impl<T: Discrete> Interval<T> {
+    pub fn new(start: T, end: T) -> Self {
+        assert!(start <= end, "Interval start should not exceed its end");
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> u128 {
+        self.start.distance(self.end) + 1
+    }
+
+    pub fn contains(&self, value: T) -> bool {
+        self.start <= value && value <= self.end
+    }
+
+    pub fn contains_interval(&self, other: &Self) -> bool {
+        self.contains(other.start) && self.contains(other.end)
+    }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        if self.overlaps(other) {
+            Some(Self::new(
+                self.start.max(other.start),
+                self.end.min(other.end),
+            ))
+        } else {
+            None
+        }
+    }
+
+    // only defined if the result is a single interval, i.e., if both
+    // intervals overlap or are directly adjacent
+    pub fn union(&self, other: &Self) -> Option<Self> {
+        let (first, second) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        if first.end.succ().is_none_or(|e| second.start <= e) {
+            Some(Self::new(first.start, first.end.max(second.end)))
+        } else {
+            None
+        }
+    }
+
+    pub fn difference(&self, other: &Self) -> Vec<Self> {
+        if !self.overlaps(other) {
+            return vec![*self];
+        }
+        let mut remainder = Vec::new();
+        if self.start < other.start {
+            remainder.push(Self::new(self.start, other.start.pred().unwrap()));
+        }
+        if other.end < self.end {
+            remainder.push(Self::new(other.end.succ().unwrap(), self.end));
+        }
+        remainder
+    }
+}
+
+// sorted, disjoint, and non-adjacent intervals
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct IntervalSet<T> {
+    intervals: Vec<Interval<T>>,
+}
+
+impl<T: Discrete> IntervalSet<T> {
+    pub fn from_intervals(intervals: &[Interval<T>]) -> Self {
+        let mut sorted = intervals.to_vec();
+        sorted.sort_unstable_by_key(|i| i.start);
+        let mut merged: Vec<Interval<T>> = Vec::new();
+        for interval in sorted {
+            if let Some(last) = merged.last_mut() {
+                if let Some(union) = last.union(&interval) {
+                    *last = union;
+                    continue;
+                }
+            }
+            merged.push(interval);
+        }
+        Self { intervals: merged }
+    }
+
+    pub fn len(&self) -> u128 {
+        self.intervals.iter().map(|i| i.len()).sum()
+    }
+
+    pub fn hull(&self) -> Option<Interval<T>> {
+        Some(Interval::new(
+            self.intervals.first()?.start,
+            self.intervals.last()?.end,
+        ))
+    }
+
+    pub fn complement_within(&self, bounds: &Interval<T>) -> Self {
+        let mut remainder = vec![*bounds];
+        for interval in self.intervals.iter() {
+            remainder = remainder
+                .iter()
+                .flat_map(|r| r.difference(interval))
+                .collect();
+        }
+        Self {
+            intervals: remainder,
+        }
+    }
+}
+
+// sweep over all interval limits and return maximal segments together with
+// the number of intervals covering them; uncovered segments are omitted
+fn coverage_segments<T: Discrete>(intervals: &[Interval<T>]) -> Vec<(Interval<T>, usize)> {
+    let mut events: Vec<(T, i64)> = Vec::new();
+    for interval in intervals {
+        events.push((interval.start, 1));
+        if let Some(after_end) = interval.end.succ() {
+            events.push((after_end, -1));
+        }
+    }
+    events.sort_unstable();
+
+    let mut segments = Vec::new();
+    let mut count = 0;
+    let mut idx = 0;
+    while idx < events.len() {
+        let position = events[idx].0;
+        while idx < events.len() && events[idx].0 == position {
+            count += events[idx].1;
+            idx += 1;
+        }
+        if count > 0 {
+            let end = if idx < events.len() {
+                events[idx].0.pred().unwrap()
+            } else {
+                T::max_value()
+            };
+            segments.push((Interval::new(position, end), count as usize));
+        }
+    }
+    segments
+}
+
+fn covered_by_more_than<T: Discrete>(intervals: &[Interval<T>], n: usize) -> IntervalSet<T> {
+    let segments = coverage_segments(intervals)
+        .into_iter()
+        .filter(|(_, count)| *count > n)
+        .map(|(segment, _)| segment)
+        .collect::<Vec<Interval<T>>>();
+    IntervalSet::from_intervals(&segments)
+}
+
+fn max_simultaneous_overlap<T: Discrete>(intervals: &[Interval<T>]) -> usize {
+    coverage_segments(intervals)
+        .into_iter()
+        .map(|(_, count)| count)
+        .max()
+        .unwrap_or(0)
+}
+
+fn parse_pairs(list: &str) -> Vec<(Interval<u64>, Interval<u64>)> {
+    list.lines()
+        .map(|l| {
+            let ranges = l.split(",").collect::<Vec<&str>>();
+            (str_to_interval(ranges[0]), str_to_interval(ranges[1]))
+        })
+        .collect()
 }
 
 fn count_pairs_with_overlap(list: &str, count_only_complete_overlap: bool) -> u64 {
     let mut count = 0;
-    for l in list.lines() {
-        let ranges = l.split(",").collect::<Vec<&str>>();
-        let range0 = str_to_numerical_range(ranges[0]);
-        let range1 = str_to_numerical_range(ranges[1]);
+    for (range0, range1) in parse_pairs(list) {
         if count_only_complete_overlap {
             if has_complete_overlap(&range0, &range1) {
                 count += 1;
@@ -31,31 +271,101 @@ fn count_pairs_with_overlap(list: &str, count_only_complete_overlap: bool) -> u6
     count
 }
 
-fn str_to_numerical_range(range: &str) -> Vec<u64> {
-    range
-        .split("-")
-        .map(|s| {
-            s.to_string()
-                .parse()
-                .expect("Should be able to convert limits to u64")
-        })
-        .collect::<Vec<u64>>()
+fn str_to_interval(range: &str) -> Interval<u64> {
+    let (start, end) = range
+        .split_once("-")
+        .expect("Should be able to split range into limits");
+    Interval::new(
+        start
+            .parse()
+            .expect("Should be able to convert limits to u64"),
+        end.parse()
+            .expect("Should be able to convert limits to u64"),
+    )
 }
 
-fn has_complete_overlap(range0: &Vec<u64>, range1: &Vec<u64>) -> bool {
-    if (range0[0] >= range1[0] && range0[1] <= range1[1])
-        || (range0[0] <= range1[0] && range0[1] >= range1[1])
-    {
-        true
-    } else {
-        false
-    }
+fn has_complete_overlap(range0: &Interval<u64>, range1: &Interval<u64>) -> bool {
+    range0.contains_interval(range1) || range1.contains_interval(range0)
+}
+
+fn has_overlap(range0: &Interval<u64>, range1: &Interval<u64>) -> bool {
+    range0.overlaps(range1)
 }
 
-fn has_overlap(range0: &Vec<u64>, range1: &Vec<u64>) -> bool {
-    if (range0[1] < range1[0]) || range0[0] > range1[1] {
-        false
-    } else {
-        true
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_pairs_with_overlap() {
+        let content = "2-4,6-8
+2-3,4-5
+5-7,7-9
+2-8,3-7
+6-6,4-6
+2-6,4-8
+1-4000000000,3999999999-18446744073709551615";
+        assert_eq!(count_pairs_with_overlap(content, true), 2);
+        assert_eq!(count_pairs_with_overlap(content, false), 5);
+    }
+
+    #[test]
+    fn test_interval() {
+        let a = Interval::new(2u64, 6);
+        let b = Interval::new(4u64, 8);
+        assert_eq!(a.len(), 5);
+        assert_eq!(Interval::new(0, u64::MAX).len(), u64::MAX as u128 + 1);
+        assert!(a.contains(6));
+        assert!(!a.contains(7));
+        assert_eq!(a.intersection(&b), Some(Interval::new(4, 6)));
+        assert_eq!(a.intersection(&Interval::new(7, 8)), None);
+        assert_eq!(a.union(&b), Some(Interval::new(2, 8)));
+        assert_eq!(a.union(&Interval::new(7, 8)), Some(Interval::new(2, 8)));
+        assert_eq!(a.union(&Interval::new(8, 9)), None);
+        assert_eq!(a.difference(&b), vec![Interval::new(2, 3)]);
+        assert_eq!(
+            a.difference(&Interval::new(3, 4)),
+            vec![Interval::new(2, 2), Interval::new(5, 6)]
+        );
+        assert_eq!(a.difference(&Interval::new(0, 10)), vec![]);
+    }
+
+    #[test]
+    fn test_interval_set_analysis() {
+        let intervals = [
+            Interval::new(2u64, 4),
+            Interval::new(6, 8),
+            Interval::new(3, 7),
+            Interval::new(12, 12),
+            Interval::new(7, u64::MAX),
+        ];
+        let assigned = IntervalSet::from_intervals(&intervals[..4]);
+        assert_eq!(
+            assigned.intervals,
+            vec![Interval::new(2, 8), Interval::new(12, 12)]
+        );
+        assert_eq!(
+            assigned
+                .complement_within(&assigned.hull().unwrap())
+                .intervals,
+            vec![Interval::new(9, 11)]
+        );
+        assert_eq!(
+            covered_by_more_than(&intervals, 1).intervals,
+            vec![
+                Interval::new(3, 4),
+                Interval::new(6, 8),
+                Interval::new(12, 12)
+            ]
+        );
+        assert_eq!(
+            covered_by_more_than(&intervals, 2).intervals,
+            vec![Interval::new(7, 7)]
+        );
+        assert_eq!(max_simultaneous_overlap(&intervals), 3);
+        assert_eq!(
+            coverage_segments(&intervals).last(),
+            Some(&(Interval::new(13, u64::MAX), 1))
+        );
     }
 }
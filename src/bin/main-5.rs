@@ -1,29 +1,322 @@
-use std::fs;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+    env, fmt, fs,
+};
 
 fn main() {
     let file_path = "inputs/5.txt";
     let contents = fs::read_to_string(file_path).expect("Should have been able to read the file");
     let (stack, instructions) = separate_stack_and_instructions(&contents);
-    let mut stack = parse_stack(&stack);
-    // apply_instructions(&mut stack, &instructions, false);
-    // let topmost_crates = stack.iter().map(|s| s.last().expect("Should be able to check crate").to_string()).collect::<Vec<String>>();
-    // println!("The topmost crates are {}.", topmost_crates.join(""));
-    apply_instructions(&mut stack, &instructions, true);
-    let topmost_crates = stack
-        .iter()
-        .map(|s| s.last().expect("Should be able to check crate").to_string())
-        .collect::<Vec<String>>();
-    println!(
-        "The topmost crates (CrateMover9001) are {}.",
-        topmost_crates.join("")
-    );
+    let stack = parse_stack(&stack);
+    let args = env::args().collect::<Vec<String>>();
+    if args.len() > 1 && args[1] == "--plan" {
+        let target = args.get(2).expect("Should provide target topmost crates");
+        let crane = crane_from_name(args.get(3).map_or("CrateMover9001", |s| s.as_str()))
+            .expect("Should provide a known crane");
+        match plan_instructions(&stack, target, crane.as_ref(), 20_000) {
+            Some(plan) => println!("{}", plan.join("\n")),
+            None => println!("No plan found for {} ({}).", target, crane.name()),
+        }
+        return;
+    }
+    let print_trace = args.iter().any(|arg| arg == "--trace");
+
+    let mut cranes: Vec<Box<dyn Crane>> = vec![
+        Box::new(CrateMover9000),
+        Box::new(CrateMover9001),
+        Box::new(CapacityLimitedMover::new(3)),
+        Box::new(AlternatingMover::new()),
+    ];
+    for crane in cranes.iter_mut() {
+        let mut stack = stack.clone();
+        let mut trace = Vec::new();
+        let result =
+            apply_instructions(&mut stack, &instructions, crane.as_mut(), Some(&mut trace));
+        if print_trace {
+            println!("{}\n", crane.name());
+            for ((_, instruction), drawing) in instructions.iter().zip(trace.iter()) {
+                println!("{}\n{}\n", instruction, drawing);
+            }
+        }
+        match result {
+            Ok(()) => println!(
+                "The topmost crates ({}) are {}.",
+                crane.name(),
+                topmost_crates(&stack)
+            ),
+            Err(err) => println!("The {} failed: {}", crane.name(), err),
+        }
+    }
+}
+
+// a crane receives the lifted crates in stack order, i.e., bottom to top, and
+// returns them in the order they are put onto the target stack
+trait Crane {
+    fn name(&self) -> String;
+    fn arrange(&mut self, crates: Vec<char>) -> Vec<char>;
+    fn clone_box(&self) -> Box<dyn Crane>;
+
+    // distinguishes otherwise identical cranes that act differently on the
+    // next move
+    fn state(&self) -> u64 {
+        0
+    }
+}
+
+fn crane_from_name(name: &str) -> Option<Box<dyn Crane>> {
+    match name {
+        "CrateMover9000" => Some(Box::new(CrateMover9000)),
+        "CrateMover9001" => Some(Box::new(CrateMover9001)),
+        "AlternatingMover" => Some(Box::new(AlternatingMover::new())),
+        _ => {
+            let capacity = name
+                .strip_prefix("CapacityLimitedMover(")?
+                .strip_suffix(')')?
+                .parse()
+                .ok()?;
+            Some(Box::new(CapacityLimitedMover::new(capacity)))
+        }
+    }
+}
+
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn name(&self) -> String {
+        "CrateMover9000".to_string()
+    }
+
+    fn arrange(&mut self, mut crates: Vec<char>) -> Vec<char> {
+        crates.reverse();
+        crates
+    }
+
+    fn clone_box(&self) -> Box<dyn Crane> {
+        Box::new(CrateMover9000)
+    }
+}
+
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn name(&self) -> String {
+        "CrateMover9001".to_string()
+    }
+
+    fn arrange(&mut self, crates: Vec<char>) -> Vec<char> {
+        crates
+    }
+
+    fn clone_box(&self) -> Box<dyn Crane> {
+        Box::new(CrateMover9001)
+    }
+}
+
+// lifts at most `capacity` crates at once, keeping their order within a lift
+struct CapacityLimitedMover {
+    capacity: usize,
+}
+
+impl CapacityLimitedMover {
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "Crane should be able to lift at least one crate"
+        );
+        Self { capacity }
+    }
+}
+
+impl Crane for CapacityLimitedMover {
+    fn name(&self) -> String {
+        format!("CapacityLimitedMover({})", self.capacity)
+    }
+
+    fn arrange(&mut self, crates: Vec<char>) -> Vec<char> {
+        crates
+            .rchunks(self.capacity)
+            .flat_map(|lift| lift.iter().cloned())
+            .collect()
+    }
+
+    fn clone_box(&self) -> Box<dyn Crane> {
+        Box::new(CapacityLimitedMover::new(self.capacity))
+    }
+}
+
+// reverses the lifted crates on every other move, starting with the first one
+struct AlternatingMover {
+    reverse_next: bool,
+}
+
+impl AlternatingMover {
+    pub fn new() -> Self {
+        Self { reverse_next: true }
+    }
 }
 
-fn separate_stack_and_instructions(content: &str) -> (Vec<String>, Vec<String>) {
+impl Crane for AlternatingMover {
+    fn name(&self) -> String {
+        "AlternatingMover".to_string()
+    }
+
+    fn arrange(&mut self, mut crates: Vec<char>) -> Vec<char> {
+        if self.reverse_next {
+            crates.reverse();
+        }
+        self.reverse_next = !self.reverse_next;
+        crates
+    }
+
+    fn clone_box(&self) -> Box<dyn Crane> {
+        Box::new(AlternatingMover {
+            reverse_next: self.reverse_next,
+        })
+    }
+
+    fn state(&self) -> u64 {
+        self.reverse_next as u64
+    }
+}
+
+#[derive(Debug)]
+enum InstructionError {
+    Malformed,
+    UnknownStack(usize),
+    NotEnoughCrates { available: usize, requested: usize },
+}
+
+#[derive(Debug)]
+struct SimulationError {
+    line: usize,
+    instruction: String,
+    kind: InstructionError,
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {} (\"{}\"): ", self.line, self.instruction)?;
+        match self.kind {
+            InstructionError::Malformed => write!(f, "malformed instruction"),
+            InstructionError::UnknownStack(stack) => write!(f, "stack {} does not exist", stack),
+            InstructionError::NotEnoughCrates {
+                available,
+                requested,
+            } => write!(
+                f,
+                "cannot move {} crates from a stack with {} crates",
+                requested, available
+            ),
+        }
+    }
+}
+
+fn topmost_crates(stack: &[Vec<char>]) -> String {
+    stack.iter().map(|s| *s.last().unwrap_or(&' ')).collect()
+}
+
+fn format_instruction(count: usize, source: usize, target: usize) -> String {
+    format!("move {} from {} to {}", count, source + 1, target + 1)
+}
+
+// each move changes at most two topmost crates
+fn estimate_remaining_moves(stack: &[Vec<char>], target: &[char]) -> usize {
+    let mismatches = topmost_crates(stack)
+        .chars()
+        .zip(target.iter())
+        .filter(|(c, t)| c != *t)
+        .count();
+    mismatches.div_ceil(2)
+}
+
+struct PlanNode {
+    stack: Vec<Vec<char>>,
+    crane: Box<dyn Crane>,
+    parent: Option<usize>,
+    instruction: String,
+    n_moves: usize,
+}
+
+// weighted A* search over stack states; finds short, but not necessarily the
+// shortest instruction lists and gives up after exploring `max_states` states
+fn plan_instructions(
+    stack: &[Vec<char>],
+    target: &str,
+    crane: &dyn Crane,
+    max_states: usize,
+) -> Option<Vec<String>> {
+    let target = target.chars().collect::<Vec<char>>();
+    if target.len() != stack.len() {
+        return None;
+    }
+    let mut nodes = vec![PlanNode {
+        stack: stack.to_vec(),
+        crane: crane.clone_box(),
+        parent: None,
+        instruction: String::new(),
+        n_moves: 0,
+    }];
+    let mut visited = HashSet::new();
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((2 * estimate_remaining_moves(stack, &target), 0)));
+    while let Some(Reverse((_, idx))) = queue.pop() {
+        if !visited.insert((nodes[idx].stack.clone(), nodes[idx].crane.state())) {
+            continue;
+        }
+        if topmost_crates(&nodes[idx].stack)
+            .chars()
+            .eq(target.iter().cloned())
+        {
+            let mut plan = Vec::new();
+            let mut current = idx;
+            while let Some(parent) = nodes[current].parent {
+                plan.push(nodes[current].instruction.clone());
+                current = parent;
+            }
+            plan.reverse();
+            return Some(plan);
+        }
+        if visited.len() >= max_states {
+            return None;
+        }
+        for source in 0..stack.len() {
+            for destination in 0..stack.len() {
+                if source == destination {
+                    continue;
+                }
+                for count in 1..=nodes[idx].stack[source].len() {
+                    let instruction = format_instruction(count, source, destination);
+                    let mut next_stack = nodes[idx].stack.clone();
+                    let mut next_crane = nodes[idx].crane.clone_box();
+                    apply_instruction(&mut next_stack, &instruction, next_crane.as_mut())
+                        .expect("Should only generate valid instructions");
+                    if visited.contains(&(next_stack.clone(), next_crane.state())) {
+                        continue;
+                    }
+                    let n_moves = nodes[idx].n_moves + 1;
+                    let priority = n_moves + 2 * estimate_remaining_moves(&next_stack, &target);
+                    queue.push(Reverse((priority, nodes.len())));
+                    nodes.push(PlanNode {
+                        stack: next_stack,
+                        crane: next_crane,
+                        parent: Some(idx),
+                        instruction,
+                        n_moves,
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+// instructions are returned along with their line numbers
+fn separate_stack_and_instructions(content: &str) -> (Vec<String>, Vec<(usize, String)>) {
     let mut stack = Vec::new();
     let mut instructions = Vec::new();
     let mut parsing_stack = true;
-    for l in content.lines() {
+    for (idx, l) in content.lines().enumerate() {
         if l.len() == 0 {
             parsing_stack = false;
             continue;
@@ -31,7 +324,7 @@ fn separate_stack_and_instructions(content: &str) -> (Vec<String>, Vec<String>)
         if parsing_stack {
             stack.push(l.to_string());
         } else {
-            instructions.push(l.to_string());
+            instructions.push((idx + 1, l.to_string()));
         }
     }
     (stack, instructions)
@@ -39,6 +332,9 @@ fn separate_stack_and_instructions(content: &str) -> (Vec<String>, Vec<String>)
 
 fn parse_stack(stack: &Vec<String>) -> Vec<Vec<char>> {
     let mut new_stack: Vec<Vec<char>> = Vec::new();
+    // the column labels determine the number of stacks, even if some are empty
+    let n_columns = stack.last().map_or(0, |l| l.split_whitespace().count());
+    new_stack.resize(n_columns, Vec::new());
     // substract one to only take the lines containing information about crates
     for l in stack.iter().take(stack.len() - 1) {
         let l_chars = l.chars().collect::<Vec<char>>();
@@ -58,46 +354,216 @@ fn parse_stack(stack: &Vec<String>) -> Vec<Vec<char>> {
     new_stack
 }
 
+// inverse of `parse_stack`, produces the drawing including the column labels
+fn render_stack(stack: &[Vec<char>]) -> Vec<String> {
+    let height = stack.iter().map(|s| s.len()).max().unwrap_or(0);
+    let mut drawing = Vec::new();
+    for level in (0..height).rev() {
+        drawing.push(
+            stack
+                .iter()
+                .map(|s| {
+                    if let Some(cr) = s.get(level) {
+                        format!("[{}]", cr)
+                    } else {
+                        "   ".to_string()
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(" "),
+        );
+    }
+    drawing.push(
+        (1..=stack.len())
+            .map(|column| format!("{:^3}", column))
+            .collect::<Vec<String>>()
+            .join(" "),
+    );
+    drawing
+}
+
+// if a trace is passed, the drawing after every successful move is recorded
 fn apply_instructions(
-    stack: &mut Vec<Vec<char>>,
-    instructions: &Vec<String>,
-    is_cratemover9001: bool,
-) {
-    for l in instructions {
-        let (count, source, target) = parse_instruction(&l);
-        if is_cratemover9001 {
-            let mut tmp_storage = Vec::new();
-            for _ in 0..count {
-                let cr = stack[source]
-                    .pop()
-                    .expect("Should be able to remove a crate");
-                tmp_storage.push(cr);
-            }
-            for _ in 0..count {
-                let cr = tmp_storage.pop().expect("Should be able to remove a crate");
-                stack[target].push(cr);
-            }
-        } else {
-            for _ in 0..count {
-                let cr = stack[source]
-                    .pop()
-                    .expect("Should be able to remove a crate");
-                stack[target].push(cr);
-            }
+    stack: &mut [Vec<char>],
+    instructions: &[(usize, String)],
+    crane: &mut dyn Crane,
+    mut trace: Option<&mut Vec<String>>,
+) -> Result<(), SimulationError> {
+    for (line, l) in instructions.iter() {
+        apply_instruction(stack, l, crane).map_err(|kind| SimulationError {
+            line: *line,
+            instruction: l.to_string(),
+            kind,
+        })?;
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.push(render_stack(stack).join("\n"));
         }
     }
+    Ok(())
 }
 
-fn parse_instruction(instruction: &str) -> (usize, usize, usize) {
+fn apply_instruction(
+    stack: &mut [Vec<char>],
+    instruction: &str,
+    crane: &mut dyn Crane,
+) -> Result<(), InstructionError> {
+    let (count, source, target) = parse_instruction(instruction)?;
+    for idx in [source, target] {
+        if idx >= stack.len() {
+            return Err(InstructionError::UnknownStack(idx + 1));
+        }
+    }
+    let available = stack[source].len();
+    if count > available {
+        return Err(InstructionError::NotEnoughCrates {
+            available,
+            requested: count,
+        });
+    }
+    let crates = stack[source].split_off(available - count);
+    let crates = crane.arrange(crates);
+    stack[target].extend(crates);
+    Ok(())
+}
+
+fn parse_instruction(instruction: &str) -> Result<(usize, usize, usize), InstructionError> {
     let instructions_split = instruction.split_whitespace().collect::<Vec<&str>>();
-    let count = instructions_split[1]
-        .parse()
-        .expect("Should be able to convert to usize");
-    let source: usize = instructions_split[3]
-        .parse()
-        .expect("Should be able to convert to usize");
-    let target: usize = instructions_split[5]
-        .parse()
-        .expect("Should be able to convert to usize");
-    (count, source - 1, target - 1)
+    if instructions_split.len() != 6
+        || instructions_split[0] != "move"
+        || instructions_split[2] != "from"
+        || instructions_split[4] != "to"
+    {
+        return Err(InstructionError::Malformed);
+    }
+    let parse = |s: &str| s.parse::<usize>().map_err(|_| InstructionError::Malformed);
+    let count = parse(instructions_split[1])?;
+    let source = parse(instructions_split[3])?;
+    let target = parse(instructions_split[5])?;
+    if source == 0 {
+        return Err(InstructionError::UnknownStack(source));
+    }
+    if target == 0 {
+        return Err(InstructionError::UnknownStack(target));
+    }
+    Ok((count, source - 1, target - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "    [D]    
+[N] [C]    
+[Z] [M] [P]
+ 1   2   3 
+
+move 1 from 2 to 1
+move 3 from 1 to 3
+move 2 from 2 to 1
+move 1 from 1 to 2";
+
+    #[test]
+    fn test_render_stack() {
+        let (drawing, instructions) = separate_stack_and_instructions(CONTENT);
+        let mut stack = parse_stack(&drawing);
+        assert_eq!(render_stack(&stack), drawing);
+
+        let mut trace = Vec::new();
+        apply_instructions(
+            &mut stack,
+            &instructions,
+            &mut CrateMover9000,
+            Some(&mut trace),
+        )
+        .unwrap();
+        assert_eq!(trace.len(), instructions.len());
+        assert_eq!(
+            trace[1],
+            "        [Z]
+        [N]
+    [C] [D]
+    [M] [P]
+ 1   2   3 "
+        );
+        let empty = vec![Vec::new(), vec!['A'], Vec::new()];
+        assert_eq!(parse_stack(&render_stack(&empty)), empty);
+    }
+
+    #[test]
+    fn test_plan_instructions() {
+        let (drawing, _) = separate_stack_and_instructions(CONTENT);
+        let stack = parse_stack(&drawing);
+        for crane in [
+            Box::new(CrateMover9000) as Box<dyn Crane>,
+            Box::new(AlternatingMover::new()),
+        ] {
+            let plan = plan_instructions(&stack, "CMZ", crane.as_ref(), 100_000).unwrap();
+            let plan = plan
+                .into_iter()
+                .enumerate()
+                .collect::<Vec<(usize, String)>>();
+            let mut stack = stack.clone();
+            apply_instructions(&mut stack, &plan, crane.clone_box().as_mut(), None).unwrap();
+            assert_eq!(topmost_crates(&stack), "CMZ");
+            assert!(plan.len() <= 4);
+        }
+        assert_eq!(
+            plan_instructions(&stack, "NDP", &CrateMover9001, 10),
+            Some(vec![])
+        );
+        assert_eq!(plan_instructions(&stack, "ND", &CrateMover9001, 10), None);
+    }
+
+    #[test]
+    fn test_cranes() {
+        let stack = vec![vec!['A', 'B', 'C', 'D', 'E', 'F'], Vec::new()];
+        let instructions = vec![(1, "move 5 from 1 to 2".to_string())];
+        for (mut crane, expected) in [
+            (Box::new(CrateMover9000) as Box<dyn Crane>, "FEDCB"),
+            (Box::new(CrateMover9001), "BCDEF"),
+            // lifts E F, then C D, then B
+            (Box::new(CapacityLimitedMover::new(2)), "EFCDB"),
+            (Box::new(AlternatingMover::new()), "FEDCB"),
+        ] {
+            let mut stack = stack.clone();
+            apply_instructions(&mut stack, &instructions, crane.as_mut(), None).unwrap();
+            assert_eq!(stack[0], vec!['A'], "{}", crane.name());
+            assert_eq!(
+                stack[1].iter().collect::<String>(),
+                expected,
+                "{}",
+                crane.name()
+            );
+        }
+
+        // the second move of the alternating crane keeps the order
+        let mut stack = stack.clone();
+        let instructions = vec![
+            (1, "move 5 from 1 to 2".to_string()),
+            (2, "move 2 from 2 to 1".to_string()),
+        ];
+        let mut crane = AlternatingMover::new();
+        apply_instructions(&mut stack, &instructions, &mut crane, None).unwrap();
+        assert_eq!(stack[0], vec!['A', 'C', 'B']);
+        assert_eq!(stack[1], vec!['F', 'E', 'D']);
+    }
+
+    #[test]
+    fn test_invalid_instruction() {
+        // the blank line between the instructions still counts
+        let content = CONTENT.replace("move 3 from 1 to 3\n", "\nmove 4 from 3 to 1\n");
+        let (drawing, instructions) = separate_stack_and_instructions(&content);
+        let mut stack = parse_stack(&drawing);
+        let err =
+            apply_instructions(&mut stack, &instructions, &mut CrateMover9001, None).unwrap_err();
+        assert_eq!(err.line, 8);
+        assert_eq!(err.instruction, "move 4 from 3 to 1");
+        assert!(matches!(
+            err.kind,
+            InstructionError::NotEnoughCrates {
+                available: 1,
+                requested: 4
+            }
+        ));
+    }
 }
@@ -1,35 +1,210 @@
-use std::{collections::HashSet, fs};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, Bytes},
+};
 
 fn main() {
     let file_name = "inputs/6.txt";
-    let content = fs::read_to_string(file_name).expect("Should be able to read file");
-    let marker_position = determine_marker_position(&content, 4);
+    let file = File::open(file_name).expect("Should be able to read file");
+    let markers =
+        find_markers(BufReader::new(file), &[4, 14]).expect("Should be able to read file");
     println!(
         "The first packet marker appears after character {}.",
-        marker_position
+        markers[0][0]
     );
-    let marker_position = determine_marker_position(&content, 14);
     println!(
         "The first message marker appears after character {}.",
-        marker_position
+        markers[1][0]
     );
+    println!(
+        "There are {} packet markers and {} message markers in total.",
+        markers[0].len(),
+        markers[1].len()
+    );
+
+    let file = File::open(file_name).expect("Should be able to read file");
+    let messages = Packetizer::new(BufReader::new(file), 14)
+        .collect::<io::Result<Vec<(usize, Vec<u8>)>>>()
+        .expect("Should be able to read file");
+    println!(
+        "The datastream contains {} messages, the longest payload has {} characters.",
+        messages.len(),
+        messages.iter().map(|(_, p)| p.len()).max().unwrap_or(0)
+    );
+}
+
+struct Window {
+    size: usize,
+    counts: [usize; 256],
+    n_distinct: usize,
+}
+
+// tracks windows of several sizes over a datastream at once, each window
+// keeps a count per byte value so advancing by one byte takes constant time
+struct MarkerDetector {
+    windows: Vec<Window>,
+    recent: VecDeque<u8>,
+    position: usize,
+}
+
+impl MarkerDetector {
+    pub fn new(window_sizes: &[usize]) -> Self {
+        assert!(
+            window_sizes.iter().all(|&size| size > 0),
+            "Window sizes should be positive"
+        );
+        Self {
+            windows: window_sizes
+                .iter()
+                .map(|&size| Window {
+                    size,
+                    counts: [0; 256],
+                    n_distinct: 0,
+                })
+                .collect(),
+            recent: VecDeque::new(),
+            position: 0,
+        }
+    }
+
+    // returns the indices of all windows that contain only distinct bytes
+    // after consuming `byte`
+    pub fn push(&mut self, byte: u8) -> Vec<usize> {
+        self.position += 1;
+        self.recent.push_back(byte);
+        let mut completed = Vec::new();
+        for (idx, window) in self.windows.iter_mut().enumerate() {
+            window.counts[byte as usize] += 1;
+            if window.counts[byte as usize] == 1 {
+                window.n_distinct += 1;
+            }
+            if self.recent.len() > window.size {
+                let dropped = self.recent[self.recent.len() - window.size - 1];
+                window.counts[dropped as usize] -= 1;
+                if window.counts[dropped as usize] == 0 {
+                    window.n_distinct -= 1;
+                }
+            }
+            if window.n_distinct == window.size {
+                completed.push(idx);
+            }
+        }
+        let max_size = self.windows.iter().map(|w| w.size).max().unwrap_or(0);
+        if self.recent.len() > max_size {
+            self.recent.pop_front();
+        }
+        completed
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
 }
 
-fn determine_marker_position(content: &str, n_distinct_characters: usize) -> usize {
-    let chars = content.chars().collect::<Vec<char>>();
-    let mut anker = 0;
-    let mut current = 0;
-    let mut markers: HashSet<char> = HashSet::new();
-    while markers.len() < n_distinct_characters && anker < content.len() {
-        let c = chars[anker + current];
-        if markers.contains(&c) {
-            markers.clear();
-            anker += 1;
-            current = 0;
-        } else {
-            markers.insert(c);
-            current += 1;
+// returns, for every window size, all positions after which a marker ends
+fn find_markers<R: BufRead>(reader: R, window_sizes: &[usize]) -> io::Result<Vec<Vec<usize>>> {
+    let mut detector = MarkerDetector::new(window_sizes);
+    let mut markers = vec![Vec::new(); window_sizes.len()];
+    for byte in reader.bytes() {
+        let byte = byte?;
+        if byte == b'\n' || byte == b'\r' {
+            continue;
         }
+        for idx in detector.push(byte) {
+            markers[idx].push(detector.position());
+        }
+    }
+    Ok(markers)
+}
+
+// splits a datastream into messages: each message starts directly after a
+// marker and its payload extends up to the next non-overlapping marker or the
+// end of the stream; data before the first marker is discarded
+struct Packetizer<R> {
+    bytes: Bytes<R>,
+    detector: MarkerDetector,
+    marker_size: usize,
+    frame: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: BufRead> Packetizer<R> {
+    pub fn new(reader: R, marker_size: usize) -> Self {
+        Self {
+            bytes: reader.bytes(),
+            detector: MarkerDetector::new(&[marker_size]),
+            marker_size,
+            frame: None,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Packetizer<R> {
+    type Item = io::Result<(usize, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let byte = match self.bytes.next() {
+                Some(Ok(byte)) => byte,
+                Some(Err(err)) => return Some(Err(err)),
+                None => return self.frame.take().map(Ok),
+            };
+            if byte == b'\n' || byte == b'\r' {
+                continue;
+            }
+            let is_marker = !self.detector.push(byte).is_empty();
+            let position = self.detector.position();
+            match self.frame.as_mut() {
+                None => {
+                    if is_marker {
+                        self.frame = Some((position, Vec::new()));
+                    }
+                }
+                Some((_, payload)) => {
+                    payload.push(byte);
+                    // the marker needs to lie completely inside the payload,
+                    // otherwise it overlaps with the previous one
+                    if is_marker && payload.len() >= self.marker_size {
+                        let (offset, mut payload) = self.frame.take().unwrap();
+                        payload.truncate(payload.len() - self.marker_size);
+                        self.frame = Some((position, Vec::new()));
+                        return Some(Ok((offset, payload)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_markers() {
+        for (content, packet, message) in [
+            ("mjqjpqmgbljsphdztnvjfqwrcgsmlb\n", 7, 19),
+            ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+        ] {
+            let markers = find_markers(content.as_bytes(), &[4, 14]).unwrap();
+            assert_eq!(markers[0][0], packet);
+            assert_eq!(markers[1][0], message);
+        }
+        assert_eq!(
+            find_markers("abcabb".as_bytes(), &[3]).unwrap(),
+            vec![vec![3, 4, 5]]
+        );
+    }
+
+    #[test]
+    fn test_packetizer() {
+        let content = "zzzabczzzzzefgyy\n";
+        let messages = Packetizer::new(content.as_bytes(), 4)
+            .collect::<io::Result<Vec<(usize, Vec<u8>)>>>()
+            .unwrap();
+        assert_eq!(messages, vec![(6, b"zzzz".to_vec()), (14, b"yy".to_vec())]);
     }
-    anker + current
 }
@@ -1,10 +1,15 @@
-use std::fs;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell, cmp::Reverse, collections::BTreeMap, env, fmt, fs, ops::RangeBounds, rc::Rc,
+};
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 struct File {
     name: String,
     size: usize,
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 struct Directory {
     name: String,
     files: Vec<File>,
@@ -20,32 +25,56 @@ impl Directory {
         }
     }
 
+    // listing the same directory twice must not duplicate entries
     fn add_subdirectory(&mut self, name: &str) {
-        self.children.push(Self::new(name));
+        if self.get_subdirectory(name).is_none() {
+            self.children.push(Self::new(name));
+        }
     }
 
     fn add_file(&mut self, name: &str, size: usize) {
-        self.files.push(File {
-            name: name.to_string(),
-            size,
-        });
+        if let Some(file) = self.files.iter_mut().find(|f| f.name == name) {
+            file.size = size;
+        } else {
+            self.files.push(File {
+                name: name.to_string(),
+                size,
+            });
+        }
     }
 
-    fn get_subdirectory(&mut self, name: &str) -> &mut Self {
-        for dir in self.children.iter_mut() {
-            if dir.name == name {
-                return dir;
-            }
-        }
-        panic!("Directory not found {} in dir {}", name, self.name);
+    fn get_subdirectory(&mut self, name: &str) -> Option<&mut Self> {
+        self.children.iter_mut().find(|dir| dir.name == name)
+    }
+
+    fn subdirectory(&self, name: &str) -> Option<&Self> {
+        self.children.iter().find(|dir| dir.name == name)
+    }
+
+    fn file(&self, name: &str) -> Option<&File> {
+        self.files.iter().find(|f| f.name == name)
+    }
+
+    fn size(&self) -> usize {
+        self.children.iter().map(|dir| dir.size()).sum::<usize>()
+            + self.files.iter().map(|f| f.size).sum::<usize>()
     }
 
     fn print(&self, depth: usize) -> String {
+        self.tree(depth, None)
+    }
+
+    // like `print`, but omits the content of directories deeper than
+    // `max_depth`
+    fn tree(&self, depth: usize, max_depth: Option<usize>) -> String {
         let prefix = (0..depth).map(|_| "  ").collect::<Vec<&str>>().join("");
         let mut s = String::new();
         s.push_str(&format!("{}- {} (dir)\n", prefix, self.name));
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return s;
+        }
         for dir in self.children.iter() {
-            s.push_str(&dir.print(depth + 1));
+            s.push_str(&dir.tree(depth + 1, max_depth));
         }
         for file in self.files.iter() {
             s.push_str(&format!(
@@ -57,122 +86,934 @@ impl Directory {
     }
 }
 
+enum Entry<'a> {
+    Directory(&'a Directory),
+    File(&'a File),
+}
+
+#[derive(Debug)]
+struct FileSystem {
+    root: Directory,
+}
+
+impl FileSystem {
+    pub fn new() -> Self {
+        Self {
+            root: Directory::new("/"),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<Entry<'_>> {
+        let components = split_path(path);
+        let Some((last, parents)) = components.split_last() else {
+            return Some(Entry::Directory(&self.root));
+        };
+        let mut dir = &self.root;
+        for name in parents {
+            dir = dir.subdirectory(name)?;
+        }
+        if let Some(subdir) = dir.subdirectory(last) {
+            Some(Entry::Directory(subdir))
+        } else {
+            dir.file(last).map(Entry::File)
+        }
+    }
+
+    pub fn size(&self, path: &str) -> Option<usize> {
+        match self.get(path)? {
+            Entry::Directory(dir) => Some(dir.size()),
+            Entry::File(file) => Some(file.size),
+        }
+    }
+
+    // all entries in depth-first order, each with its absolute path
+    pub fn walk(&self) -> Vec<(String, Entry<'_>)> {
+        let mut entries = Vec::new();
+        walk_directory(&self.root, "/".to_string(), &mut entries);
+        entries
+    }
+
+    // recursive sizes of all directories with their absolute paths
+    pub fn du<R: RangeBounds<usize>>(&self, sizes: R, order: SortOrder) -> Vec<(String, usize)> {
+        let mut usage = Vec::new();
+        determine_sizes_of_all_directories(&self.root, "/".to_string(), &mut usage);
+        usage.retain(|(_, size)| sizes.contains(size));
+        match order {
+            SortOrder::Path => usage.sort_unstable(),
+            SortOrder::SizeAscending => {
+                usage.sort_unstable_by_key(|(path, size)| (*size, path.clone()))
+            }
+            SortOrder::SizeDescending => {
+                usage.sort_unstable_by_key(|(path, size)| (Reverse(*size), path.clone()))
+            }
+        }
+        usage
+    }
+
+    // all files whose name matches `pattern`, see `glob_match`
+    pub fn find(&self, pattern: &str) -> Vec<(String, usize)> {
+        self.walk()
+            .into_iter()
+            .filter_map(|(path, entry)| match entry {
+                Entry::File(file) if glob_match(pattern, &file.name) => Some((path, file.size)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn tree(&self, max_depth: Option<usize>) -> String {
+        self.root.tree(0, max_depth)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.root).expect("Should be able to serialize directory")
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        Ok(Self {
+            root: serde_json::from_str(json)?,
+        })
+    }
+
+    // maps every absolute path to its kind and (recursive) size
+    fn sizes_by_path(&self) -> BTreeMap<String, (EntryKind, usize)> {
+        let mut sizes = BTreeMap::new();
+        let mut directory_sizes = Vec::new();
+        determine_sizes_of_all_directories(&self.root, "/".to_string(), &mut directory_sizes);
+        for (path, size) in directory_sizes {
+            sizes.insert(path, (EntryKind::Directory, size));
+        }
+        for (path, entry) in self.walk() {
+            if let Entry::File(file) = entry {
+                sizes.insert(path, (EntryKind::File, file.size));
+            }
+        }
+        sizes
+    }
+
+    fn directory_mut(&mut self, path: &[String]) -> Option<&mut Directory> {
+        let mut dir = &mut self.root;
+        for name in path {
+            dir = dir.get_subdirectory(name)?;
+        }
+        Some(dir)
+    }
+}
+
+fn walk_directory<'a>(dir: &'a Directory, path: String, entries: &mut Vec<(String, Entry<'a>)>) {
+    entries.push((path.clone(), Entry::Directory(dir)));
+    for subdir in dir.children.iter() {
+        walk_directory(subdir, join_path(&path, &subdir.name), entries);
+    }
+    for file in dir.files.iter() {
+        entries.push((join_path(&path, &file.name), Entry::File(file)));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EntryKind {
+    Directory,
+    File,
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntryKind::Directory => write!(f, "dir"),
+            EntryKind::File => write!(f, "file"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    Added(String, EntryKind, usize),
+    Removed(String, EntryKind, usize),
+    Resized(String, EntryKind, usize, usize),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added(path, kind, size) => write!(f, "+ {} ({}, size={})", path, kind, size),
+            Change::Removed(path, kind, size) => {
+                write!(f, "- {} ({}, size={})", path, kind, size)
+            }
+            Change::Resized(path, kind, old, new) => {
+                write!(f, "~ {} ({}, size={} -> {})", path, kind, old, new)
+            }
+        }
+    }
+}
+
+// structural difference between two snapshots ordered by path; directories
+// count as resized whenever their recursive size changes, and an entry that
+// changed its kind is reported as removed and added
+fn diff(old: &FileSystem, new: &FileSystem) -> Vec<Change> {
+    let old_sizes = old.sizes_by_path();
+    let new_sizes = new.sizes_by_path();
+    let mut changes = Vec::new();
+    for (path, &(kind, size)) in old_sizes.iter() {
+        match new_sizes.get(path) {
+            Some(&(new_kind, new_size)) if new_kind == kind => {
+                if new_size != size {
+                    changes.push(Change::Resized(path.clone(), kind, size, new_size));
+                }
+            }
+            _ => changes.push(Change::Removed(path.clone(), kind, size)),
+        }
+    }
+    for (path, &(kind, size)) in new_sizes.iter() {
+        if old_sizes
+            .get(path)
+            .is_none_or(|&(old_kind, _)| old_kind != kind)
+        {
+            changes.push(Change::Added(path.clone(), kind, size));
+        }
+    }
+    changes.sort_by(|a, b| change_path(a).cmp(change_path(b)));
+    changes
+}
+
+fn change_path(change: &Change) -> &str {
+    match change {
+        Change::Added(path, ..) | Change::Removed(path, ..) | Change::Resized(path, ..) => path,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SortOrder {
+    Path,
+    SizeAscending,
+    SizeDescending,
+}
+
+// supports `*` for any sequence of characters and `?` for a single character
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern and the name position it
+    // currently matches up to
+    let mut backtrack = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/').filter(|c| !c.is_empty()).collect()
+}
+
+fn join_path(path: &str, name: &str) -> String {
+    if path.ends_with('/') {
+        format!("{}{}", path, name)
+    } else {
+        format!("{}/{}", path, name)
+    }
+}
+
+#[derive(Debug)]
+enum TranscriptError {
+    UnknownCommand(String),
+    MissingArgument(String),
+    UnknownDirectory(String),
+    OutputWithoutListing,
+    MalformedEntry,
+}
+
+#[derive(Debug)]
+struct ReplayError {
+    line: usize,
+    kind: TranscriptError,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            TranscriptError::UnknownCommand(cmd) => write!(f, "unknown command {}", cmd),
+            TranscriptError::MissingArgument(cmd) => write!(f, "{} requires an argument", cmd),
+            TranscriptError::UnknownDirectory(path) => {
+                write!(f, "directory {} does not exist", path)
+            }
+            TranscriptError::OutputWithoutListing => write!(f, "output outside of ls"),
+            TranscriptError::MalformedEntry => write!(f, "malformed directory entry"),
+        }
+    }
+}
+
 fn main() {
     let file_path = "inputs/7.txt";
     let content = fs::read_to_string(file_path).expect("Should be able to read file");
-    let root = build_directory_tree(&content);
-    println!("{}", root.print(0));
+    let fs = match build_directory_tree(&content) {
+        Ok(fs) => fs,
+        Err(err) => {
+            println!("Could not replay transcript: {}", err);
+            return;
+        }
+    };
+
+    let args = env::args().collect::<Vec<String>>();
+    if args.len() > 1 {
+        run_command(&fs, &args[1..]);
+        return;
+    }
 
-    let mut sizes = Vec::new();
-    determine_sizes_of_all_directories(&root, &mut sizes);
-    let total_size_small_directories = sizes.iter().filter(|&size| *size < 100_000).sum::<usize>();
-    println!("The total size is {}.", total_size_small_directories);
+    println!("{}", fs.root.print(0));
+    let entries = fs.walk();
+    let n_directories = entries
+        .iter()
+        .filter(|(_, e)| matches!(e, Entry::Directory(_)))
+        .count();
+    println!(
+        "The filesystem contains {} directories and {} files.",
+        n_directories,
+        entries.len() - n_directories
+    );
+
+    let small_directory_threshold = 100_000;
+    println!(
+        "The total size is {}.",
+        total_size_of_small_directories(&fs, small_directory_threshold)
+    );
 
     let total_disk_space = 70_000_000;
     let required_disk_space = 30_000_000;
-    let used_disk_space = sizes.iter().max().unwrap();
-    let unused_disk_space = total_disk_space - used_disk_space;
-    let necessary_to_free = required_disk_space - unused_disk_space;
-
-    let x = sizes
-        .iter()
-        .filter(|&size| *size > necessary_to_free)
-        .min()
+    let (path, size) = smallest_directory_to_free(&fs, total_disk_space, required_disk_space)
         .expect("Should be able to determine directory");
     println!(
-        "The small directory that would free up enough space has size {}.",
-        x
+        "The small directory that would free up enough space is {} with size {}.",
+        path, size
     );
+    for (include_files, objective) in [(false, Objective::MinFreed), (true, Objective::MinFreed)] {
+        if let Some(plan) = plan_deletions(
+            &fs,
+            total_disk_space,
+            required_disk_space,
+            include_files,
+            objective,
+        ) {
+            println!(
+                "Deleting {} frees up {}{}.",
+                plan.paths.join(", "),
+                plan.freed,
+                if plan.approximate {
+                    " (possibly not the least)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
 }
 
-fn build_directory_tree(content: &str) -> Directory {
-    let mut lines = content.lines().into_iter();
-    let mut root = Directory::new("/");
-    let mut current = &mut root;
-    let mut path = Vec::new();
-    while let Some(l) = lines.next() {
-        if is_command(l) {
-            let (cmd, arg) = parse_command(l);
-            match cmd.as_str() {
-                "cd" => {
-                    let arg = arg.unwrap();
-                    match arg.as_str() {
-                        "/" => (),
-                        ".." => {
-                            path.pop();
-                            current = navigate_to_path(&mut root, &path);
-                        }
-                        _ => {
-                            path.push(arg.to_string());
-                            current = current.get_subdirectory(&arg);
-                        }
+// du [min size] [max size] [path|size|size-desc], find <glob>, tree [max depth],
+// export <snapshot>, diff <old snapshot> [new snapshot],
+// plan [min-freed|min-deletions] [total space] [required space] [files]
+fn run_command(fs: &FileSystem, args: &[String]) {
+    let parse_size = |idx: usize, default: usize| {
+        args.get(idx).map_or(default, |a| {
+            a.parse().expect("Should be able to parse number")
+        })
+    };
+    match args[0].as_str() {
+        "du" => {
+            let order = match args.get(3).map(|a| a.as_str()) {
+                None | Some("path") => SortOrder::Path,
+                Some("size") => SortOrder::SizeAscending,
+                Some("size-desc") => SortOrder::SizeDescending,
+                Some(order) => panic!("Unknown sort order {}", order),
+            };
+            for (path, size) in fs.du(parse_size(1, 0)..=parse_size(2, usize::MAX), order) {
+                println!("{}\t{}", size, path);
+            }
+        }
+        "find" => {
+            let pattern = args.get(1).expect("Should provide a pattern");
+            for (path, _) in fs.find(pattern) {
+                println!("{}", path);
+            }
+        }
+        "export" => {
+            let path = args.get(1).expect("Should provide a snapshot path");
+            fs::write(path, fs.to_json()).expect("Should be able to write snapshot");
+        }
+        "diff" => {
+            let load = |path: &String| {
+                let json = fs::read_to_string(path).expect("Should be able to read snapshot");
+                FileSystem::from_json(&json).expect("Should be able to parse snapshot")
+            };
+            let old = load(args.get(1).expect("Should provide a snapshot path"));
+            // without a second snapshot, compare against the current transcript
+            let new = args.get(2).map(load);
+            for change in diff(&old, new.as_ref().unwrap_or(fs)) {
+                println!("{}", change);
+            }
+        }
+        "plan" => {
+            let objective = match args.get(1).map(|a| a.as_str()) {
+                None | Some("min-freed") => Objective::MinFreed,
+                Some("min-deletions") => Objective::MinDeletions,
+                Some(objective) => panic!("Unknown objective {}", objective),
+            };
+            let include_files = args.get(4).is_some_and(|a| a == "files");
+            match plan_deletions(
+                fs,
+                parse_size(2, 70_000_000),
+                parse_size(3, 30_000_000),
+                include_files,
+                objective,
+            ) {
+                Some(plan) => {
+                    for path in plan.paths.iter() {
+                        println!("{}", path);
+                    }
+                    println!("{} freed in total", plan.freed);
+                    if plan.approximate {
+                        println!("The search was cut short, a better plan may exist.");
                     }
                 }
-                "ls" => {}
-                _ => panic!("Unknown command {}", cmd),
+                None => println!("Not enough space can be freed."),
             }
-        } else {
-            if is_dir(l) {
-                let name = parse_directory_name(l);
-                current.add_subdirectory(&name);
-            } else {
-                let (name, size) = parse_filename_and_size(l);
-                current.add_file(&name, size);
+        }
+        "tree" => {
+            print!("{}", fs.tree(args.get(1).map(|_| parse_size(1, 0))));
+        }
+        _ => panic!("Unknown command {}", args[0]),
+    }
+}
+
+fn total_size_of_small_directories(fs: &FileSystem, threshold: usize) -> usize {
+    fs.du(..threshold, SortOrder::Path)
+        .iter()
+        .map(|(_, size)| size)
+        .sum()
+}
+
+fn smallest_directory_to_free(
+    fs: &FileSystem,
+    total_disk_space: usize,
+    required_disk_space: usize,
+) -> Option<(String, usize)> {
+    let necessary_to_free = space_to_free(fs, total_disk_space, required_disk_space)?;
+    fs.du(necessary_to_free + 1.., SortOrder::SizeAscending)
+        .into_iter()
+        .next()
+}
+
+fn space_to_free(
+    fs: &FileSystem,
+    total_disk_space: usize,
+    required_disk_space: usize,
+) -> Option<usize> {
+    let used_disk_space = fs.size("/")?;
+    let unused_disk_space = total_disk_space.saturating_sub(used_disk_space);
+    Some(required_disk_space.saturating_sub(unused_disk_space))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Objective {
+    MinFreed,
+    MinDeletions,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct DeletionPlan {
+    paths: Vec<String>,
+    freed: usize,
+    // set if partial plans had to be dropped to stay within
+    // `MAX_PARTIAL_PLANS`, in which case a plan freeing less space may exist
+    approximate: bool,
+}
+
+// upper bound on the number of partial plans kept per directory when
+// minimising the freed space
+const MAX_PARTIAL_PLANS: usize = 500;
+
+// deleted entries of a partial plan, stored as a tree so that combining two
+// partial plans does not need to copy their paths
+enum Deleted {
+    Nothing,
+    Entry(String),
+    Both(Rc<Deleted>, Rc<Deleted>),
+}
+
+impl Deleted {
+    fn collect_paths(&self, paths: &mut Vec<String>) {
+        match self {
+            Deleted::Nothing => (),
+            Deleted::Entry(path) => paths.push(path.clone()),
+            Deleted::Both(first, second) => {
+                first.collect_paths(paths);
+                second.collect_paths(paths);
             }
         }
     }
-    root
 }
 
-fn is_command(l: &str) -> bool {
-    l.chars().next().unwrap() == '$'
+#[derive(Clone)]
+struct PartialPlan {
+    freed: usize,
+    n_deletions: usize,
+    deleted: Rc<Deleted>,
 }
 
-fn parse_command(l: &str) -> (String, Option<String>) {
-    assert!(l.chars().next().unwrap() == '$');
-    let l_split = l.split_whitespace().collect::<Vec<&str>>();
-    let cmd = l_split[1].to_string();
-    let arg;
-    if l_split.len() > 2 {
-        arg = Some(l_split[2].to_string());
+impl PartialPlan {
+    fn empty() -> Self {
+        Self {
+            freed: 0,
+            n_deletions: 0,
+            deleted: Rc::new(Deleted::Nothing),
+        }
+    }
+
+    fn single(path: String, size: usize) -> Self {
+        Self {
+            freed: size,
+            n_deletions: 1,
+            deleted: Rc::new(Deleted::Entry(path)),
+        }
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Self {
+            freed: self.freed + other.freed,
+            n_deletions: self.n_deletions + other.n_deletions,
+            deleted: Rc::new(Deleted::Both(self.deleted.clone(), other.deleted.clone())),
+        }
+    }
+
+    fn cost(&self, objective: Objective) -> (usize, usize) {
+        match objective {
+            Objective::MinFreed => (self.freed, self.n_deletions),
+            Objective::MinDeletions => (self.n_deletions, self.freed),
+        }
+    }
+}
+
+// chooses directories other than the root, and optionally individual files,
+// such that no chosen entry contains another and their deletion frees at
+// least the necessary space
+fn plan_deletions(
+    fs: &FileSystem,
+    total_disk_space: usize,
+    required_disk_space: usize,
+    include_files: bool,
+    objective: Objective,
+) -> Option<DeletionPlan> {
+    let necessary_to_free = space_to_free(fs, total_disk_space, required_disk_space)?;
+    let planner = DeletionPlanner {
+        include_files,
+        objective,
+        necessary_to_free,
+        subsampled: Cell::new(false),
+    };
+    let best = planner
+        .plan_content(&fs.root, "/")
+        .into_iter()
+        .filter(|p| p.freed >= necessary_to_free)
+        .min_by_key(|p| p.cost(objective))?;
+    let mut paths = Vec::new();
+    best.deleted.collect_paths(&mut paths);
+    Some(DeletionPlan {
+        paths,
+        freed: best.freed,
+        approximate: planner.subsampled.get(),
+    })
+}
+
+struct DeletionPlanner {
+    include_files: bool,
+    objective: Objective,
+    necessary_to_free: usize,
+    subsampled: Cell<bool>,
+}
+
+impl DeletionPlanner {
+    // partial plans that only delete entries inside of `dir`
+    fn plan_content(&self, dir: &Directory, path: &str) -> Vec<PartialPlan> {
+        let mut plans = vec![PartialPlan::empty()];
+        for subdir in dir.children.iter() {
+            let subdir_path = join_path(path, &subdir.name);
+            let mut options = self.plan_content(subdir, &subdir_path);
+            options.push(PartialPlan::single(subdir_path, subdir.size()));
+            plans = self.combine(&plans, &self.prune(options));
+        }
+        if self.include_files {
+            for file in dir.files.iter() {
+                let options = [
+                    PartialPlan::empty(),
+                    PartialPlan::single(join_path(path, &file.name), file.size),
+                ];
+                plans = self.combine(&plans, &options);
+            }
+        }
+        plans
+    }
+
+    fn combine(&self, plans: &[PartialPlan], options: &[PartialPlan]) -> Vec<PartialPlan> {
+        let mut combined = Vec::with_capacity(plans.len() * options.len());
+        for plan in plans {
+            for option in options {
+                combined.push(plan.combine(option));
+            }
+        }
+        self.prune(combined)
+    }
+
+    // drops partial plans that can not lead to a better complete plan: a plan
+    // that already frees enough space is only worth keeping if it is the best
+    // one so far, and otherwise a plan is dominated by one that frees at least
+    // as much with at most as many deletions (minimising deletions) or frees
+    // the same amount with fewer deletions (minimising freed space)
+    fn prune(&self, mut plans: Vec<PartialPlan>) -> Vec<PartialPlan> {
+        let best_complete = plans
+            .iter()
+            .filter(|p| p.freed >= self.necessary_to_free)
+            .min_by_key(|p| p.cost(self.objective))
+            .cloned();
+        plans.retain(|p| p.freed < self.necessary_to_free);
+        match self.objective {
+            Objective::MinDeletions => {
+                plans.sort_unstable_by_key(|p| (p.n_deletions, Reverse(p.freed)));
+                let mut most_freed = None;
+                plans.retain(|p| {
+                    if most_freed.is_some_and(|freed| freed >= p.freed) {
+                        return false;
+                    }
+                    most_freed = Some(p.freed);
+                    true
+                });
+                if let Some(best) = best_complete.as_ref() {
+                    plans.retain(|p| p.n_deletions < best.n_deletions);
+                }
+            }
+            Objective::MinFreed => {
+                plans.sort_unstable_by_key(|p| (p.freed, p.n_deletions));
+                plans.dedup_by_key(|p| p.freed);
+                if plans.len() > MAX_PARTIAL_PLANS {
+                    self.subsampled.set(true);
+                    let step = plans.len() as f64 / MAX_PARTIAL_PLANS as f64;
+                    plans = (0..MAX_PARTIAL_PLANS)
+                        .map(|i| plans[(i as f64 * step) as usize].clone())
+                        .collect();
+                }
+            }
+        }
+        plans.extend(best_complete);
+        plans
+    }
+}
+
+fn build_directory_tree(content: &str) -> Result<FileSystem, ReplayError> {
+    let mut fs = FileSystem::new();
+    let mut path: Vec<String> = Vec::new();
+    let mut listing = false;
+    for (idx, l) in content.lines().enumerate() {
+        replay_line(&mut fs, &mut path, &mut listing, l).map_err(|kind| ReplayError {
+            line: idx + 1,
+            kind,
+        })?;
+    }
+    Ok(fs)
+}
+
+fn replay_line(
+    fs: &mut FileSystem,
+    path: &mut Vec<String>,
+    listing: &mut bool,
+    l: &str,
+) -> Result<(), TranscriptError> {
+    if l.trim().is_empty() {
+        return Ok(());
+    }
+    if is_command(l) {
+        *listing = false;
+        let (cmd, arg) = parse_command(l);
+        match cmd.as_str() {
+            "cd" => {
+                let arg = arg.ok_or_else(|| TranscriptError::MissingArgument(cmd.to_string()))?;
+                let new_path = resolve_path(path, &arg);
+                if fs.directory_mut(&new_path).is_none() {
+                    return Err(TranscriptError::UnknownDirectory(format!(
+                        "/{}",
+                        new_path.join("/")
+                    )));
+                }
+                *path = new_path;
+            }
+            "ls" => *listing = true,
+            _ => return Err(TranscriptError::UnknownCommand(cmd)),
+        }
     } else {
-        arg = None
+        if !*listing {
+            return Err(TranscriptError::OutputWithoutListing);
+        }
+        let current = fs
+            .directory_mut(path)
+            .expect("Should only navigate to existing directories");
+        if is_dir(l) {
+            let name = parse_directory_name(l).ok_or(TranscriptError::MalformedEntry)?;
+            current.add_subdirectory(&name);
+        } else {
+            let (name, size) = parse_filename_and_size(l).ok_or(TranscriptError::MalformedEntry)?;
+            current.add_file(&name, size);
+        }
     }
-    (cmd, arg)
+    Ok(())
 }
 
-fn navigate_to_path<'a>(root: &'a mut Directory, path: &Vec<String>) -> &'a mut Directory {
-    let mut dir = root;
-    for dir_name in path {
-        dir = dir.get_subdirectory(dir_name);
+// resolves absolute and relative paths, including `..`, against the current
+// working directory; `..` at the root stays at the root
+fn resolve_path(cwd: &[String], path: &str) -> Vec<String> {
+    let mut resolved = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        cwd.to_vec()
+    };
+    for component in split_path(path) {
+        match component {
+            "." => (),
+            ".." => {
+                resolved.pop();
+            }
+            _ => resolved.push(component.to_string()),
+        }
     }
-    dir
+    resolved
+}
+
+fn is_command(l: &str) -> bool {
+    l.starts_with('$')
+}
+
+fn parse_command(l: &str) -> (String, Option<String>) {
+    assert!(l.starts_with('$'));
+    let l_split = l.split_whitespace().collect::<Vec<&str>>();
+    let cmd = l_split.get(1).unwrap_or(&"").to_string();
+    let arg = l_split.get(2).map(|a| a.to_string());
+    (cmd, arg)
 }
 
 fn is_dir(l: &str) -> bool {
     l.split_whitespace().next().unwrap() == "dir"
 }
 
-fn parse_directory_name(l: &str) -> String {
-    l.split_whitespace().last().unwrap().to_string()
+fn parse_directory_name(l: &str) -> Option<String> {
+    let l_split = l.split_whitespace().collect::<Vec<&str>>();
+    if l_split.len() != 2 {
+        return None;
+    }
+    Some(l_split[1].to_string())
 }
 
-fn parse_filename_and_size(l: &str) -> (String, usize) {
+fn parse_filename_and_size(l: &str) -> Option<(String, usize)> {
     let l_split = l.split_whitespace().collect::<Vec<&str>>();
-    (
-        l_split[1].to_string(),
-        l_split[0].parse().expect("Should be able to parse size"),
-    )
+    if l_split.len() != 2 {
+        return None;
+    }
+    Some((l_split[1].to_string(), l_split[0].parse().ok()?))
 }
 
-fn determine_sizes_of_all_directories(dir: &Directory, sizes: &mut Vec<usize>) -> usize {
+fn determine_sizes_of_all_directories(
+    dir: &Directory,
+    path: String,
+    sizes: &mut Vec<(String, usize)>,
+) -> usize {
     let mut size = 0;
     for subdir in dir.children.iter() {
-        size += determine_sizes_of_all_directories(subdir, sizes);
+        size += determine_sizes_of_all_directories(subdir, join_path(&path, &subdir.name), sizes);
     }
     for file in dir.files.iter() {
         size += file.size;
     }
-    sizes.push(size);
+    sizes.push((path, size));
     size
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = "$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k";
+
+    #[test]
+    fn test_build_directory_tree() {
+        let fs = build_directory_tree(TRANSCRIPT).unwrap();
+        assert_eq!(fs.size("/a/e"), Some(584));
+        assert_eq!(fs.size("/a"), Some(94853));
+        assert_eq!(fs.size("/d/k"), Some(7214296));
+        assert_eq!(fs.size("/"), Some(48381165));
+        assert_eq!(fs.size("/x"), None);
+        assert_eq!(fs.walk().len(), 14);
+
+        // listing a directory again does not duplicate its entries
+        let content = format!(
+            "{}\n$ cd /a/e\n$ ls\n584 i\n$ cd /\n$ ls\ndir a",
+            TRANSCRIPT
+        );
+        let fs = build_directory_tree(&content).unwrap();
+        assert_eq!(fs.size("/"), Some(48381165));
+        assert_eq!(fs.walk().len(), 14);
+    }
+
+    #[test]
+    fn test_queries() {
+        let fs = build_directory_tree(TRANSCRIPT).unwrap();
+        assert_eq!(
+            fs.du(.., SortOrder::SizeDescending),
+            vec![
+                ("/".to_string(), 48381165),
+                ("/d".to_string(), 24933642),
+                ("/a".to_string(), 94853),
+                ("/a/e".to_string(), 584),
+            ]
+        );
+        assert_eq!(total_size_of_small_directories(&fs, 100_000), 95437);
+        assert_eq!(
+            smallest_directory_to_free(&fs, 70_000_000, 30_000_000),
+            Some(("/d".to_string(), 24933642))
+        );
+        assert_eq!(
+            fs.find("*.*")
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<String>>(),
+            vec!["/a/h.lst", "/d/d.log", "/d/d.ext", "/b.txt", "/c.dat"]
+        );
+        assert_eq!(fs.find("d.?x*").len(), 1);
+        assert_eq!(
+            fs.tree(Some(1)),
+            "- / (dir)\n  - a (dir)\n  - d (dir)\n  - b.txt (file, size=14848514)\n  - c.dat (file, size=8504156)\n"
+        );
+    }
+
+    #[test]
+    fn test_replay_errors() {
+        let err = build_directory_tree("$ cd /\n$ ls\ndir a\n$ cd b").unwrap_err();
+        assert_eq!(err.line, 4);
+        assert!(matches!(err.kind, TranscriptError::UnknownDirectory(_)));
+        let err = build_directory_tree("$ cd /\n$ rm a").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(matches!(err.kind, TranscriptError::UnknownCommand(_)));
+        let err = build_directory_tree("$ cd /\ndir a").unwrap_err();
+        assert!(matches!(err.kind, TranscriptError::OutputWithoutListing));
+        for entry in ["dir", "dir a b", "12"] {
+            let err = build_directory_tree(&format!("$ cd /\n$ ls\n{}", entry)).unwrap_err();
+            assert_eq!(err.line, 3);
+            assert!(matches!(err.kind, TranscriptError::MalformedEntry));
+        }
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let fs = build_directory_tree(TRANSCRIPT).unwrap();
+        let snapshot = FileSystem::from_json(&fs.to_json()).unwrap();
+        assert_eq!(snapshot.root, fs.root);
+        assert_eq!(diff(&fs, &snapshot), vec![]);
+
+        let content = format!(
+            "{}\n$ cd /a/e\n$ ls\n600 i\n$ cd /d\n$ ls\ndir x\n$ cd /\n$ ls\ndir k",
+            TRANSCRIPT.replace("7214296 k", "")
+        );
+        let changed = build_directory_tree(&content).unwrap();
+        assert_eq!(
+            diff(&fs, &changed),
+            vec![
+                Change::Resized("/".to_string(), EntryKind::Directory, 48381165, 41166885),
+                Change::Resized("/a".to_string(), EntryKind::Directory, 94853, 94869),
+                Change::Resized("/a/e".to_string(), EntryKind::Directory, 584, 600),
+                Change::Resized("/a/e/i".to_string(), EntryKind::File, 584, 600),
+                Change::Resized("/d".to_string(), EntryKind::Directory, 24933642, 17719346),
+                Change::Removed("/d/k".to_string(), EntryKind::File, 7214296),
+                Change::Added("/d/x".to_string(), EntryKind::Directory, 0),
+                Change::Added("/k".to_string(), EntryKind::Directory, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_deletions() {
+        let fs = build_directory_tree(TRANSCRIPT).unwrap();
+        // 8381165 need to be freed
+        assert_eq!(
+            plan_deletions(&fs, 70_000_000, 30_000_000, false, Objective::MinFreed),
+            Some(DeletionPlan {
+                paths: vec!["/d".to_string()],
+                freed: 24933642,
+                approximate: false
+            })
+        );
+        assert_eq!(
+            plan_deletions(&fs, 70_000_000, 30_000_000, true, Objective::MinFreed),
+            Some(DeletionPlan {
+                paths: vec!["/c.dat".to_string()],
+                freed: 8504156,
+                approximate: false
+            })
+        );
+        assert_eq!(
+            plan_deletions(&fs, 70_000_000, 30_000_000, true, Objective::MinDeletions)
+                .map(|p| p.paths.len()),
+            Some(1)
+        );
+        assert_eq!(
+            plan_deletions(&fs, 50_000_000, 30_000_000, true, Objective::MinDeletions)
+                .map(|p| p.paths),
+            Some(vec!["/d".to_string(), "/c.dat".to_string()])
+        );
+        assert_eq!(
+            plan_deletions(&fs, 1_000_000, 30_000_000, false, Objective::MinFreed),
+            None
+        );
+
+        // files of sizes 1, 2, 4, ..., 512 can free any amount up to 1023,
+        // which exceeds the number of partial plans kept
+        let mut transcript = "$ cd /\n$ ls\n".to_string();
+        for i in 0..10 {
+            transcript.push_str(&format!("{} f{}\n", 1 << i, i));
+        }
+        let fs = build_directory_tree(&transcript).unwrap();
+        let plan = plan_deletions(&fs, 1023, 1000, true, Objective::MinFreed).unwrap();
+        assert!(plan.approximate);
+        assert!(plan.freed >= 1000);
+        let plan = plan_deletions(&fs, 1023, 1000, true, Objective::MinDeletions).unwrap();
+        assert!(!plan.approximate);
+    }
+}
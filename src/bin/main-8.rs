@@ -1,26 +1,72 @@
-use std::fs;
+use std::{cmp::Reverse, collections::BinaryHeap, env, fs};
 
 fn main() {
     let file_name = "inputs/8.txt";
-    let content = fs::read_to_string(file_name).expect("Should be able to read file");
-    let map = parse_map(&content);
-    // print(&map);
-    let visibility_map = compute_visibility_map(&map);
-    // print(&visibility_map);
+    let args = env::args().collect::<Vec<String>>();
+    let map = if args.len() > 2 && args[1] == "--generate" {
+        let size = args[2].parse().expect("Should be able to parse size");
+        let cols = args.get(3).and_then(|c| c.parse().ok()).unwrap_or(size);
+        generate_forest(size, cols, 42)
+    } else {
+        let content = fs::read_to_string(file_name).expect("Should be able to read file");
+        parse_map(&content)
+    };
+    let (visibility_map, scenic_map) = scan_forest(&map);
     let n_visible = visibility_map
         .iter()
         .map(|v| v.iter().sum::<u32>())
         .sum::<u32>();
     println!("{} trees are visible from the outside.", n_visible);
 
-    let scenic_map = compute_scenic_map(&map);
-    // print(&scenic_map);
-    let scenic_score = scenic_map
-        .iter()
-        .map(|v| v.iter().max().unwrap())
-        .max()
-        .unwrap();
-    println!("The best tree has a scenic score of {}.", scenic_score);
+    let best = best_tree(&scenic_map).expect("Should have at least one tree");
+    println!(
+        "The best tree has a scenic score of {}.",
+        scenic_map[best.0][best.1]
+    );
+
+    // --treehouses <k> [extra height]
+    if let Some(idx) = args.iter().position(|a| a == "--treehouses") {
+        let k = args.get(idx + 1).and_then(|a| a.parse().ok()).unwrap_or(3);
+        let extra_height = args.get(idx + 2).and_then(|a| a.parse().ok()).unwrap_or(0);
+        // eight distances can overflow on large forests
+        let product = |distances: &[usize]| {
+            distances
+                .iter()
+                .fold(1u64, |acc, &d| acc.saturating_mul(d as u64))
+        };
+        for (position, score) in top_treehouses(&map, k, extra_height, product) {
+            let visible = look_around(&map, position, extra_height)
+                .iter()
+                .map(|trees| trees.len())
+                .sum::<usize>();
+            println!(
+                "A treehouse at {:?} has a score of {} and a view of {} trees.",
+                position, score, visible
+            );
+        }
+    }
+
+    // --export <directory> [--colormap] [--mark-best]
+    if let Some(idx) = args.iter().position(|a| a == "--export") {
+        let directory = args.get(idx + 1).expect("Should provide a directory");
+        let colormap = args.iter().any(|a| a == "--colormap");
+        let marker = args.iter().any(|a| a == "--mark-best").then_some(best);
+        for (name, values) in [
+            ("height", widen(&map)),
+            ("visibility", widen(&visibility_map)),
+            ("scenic", scenic_map),
+        ] {
+            let image = encode_image(&values, colormap, marker);
+            let extension = if image.starts_with(b"P5") {
+                "pgm"
+            } else {
+                "ppm"
+            };
+            let path = format!("{}/{}.{}", directory, name, extension);
+            fs::write(&path, image).expect("Should be able to write image");
+            println!("Wrote {}.", path);
+        }
+    }
 }
 
 fn parse_map(content: &str) -> Vec<Vec<u32>> {
@@ -34,112 +80,342 @@ fn parse_map(content: &str) -> Vec<Vec<u32>> {
     map
 }
 
-fn compute_visibility_map(map: &Vec<Vec<u32>>) -> Vec<Vec<u32>> {
-    let mut visibility_map = Vec::new();
-    for i in 0..map.len() {
-        visibility_map.push(Vec::new());
-        visibility_map[i].resize(map[i].len(), 0);
+// keeps the trees along a line of sight that can still block the view of
+// trees further along the line, i.e., a stack of strictly decreasing heights
+struct LineScanner {
+    stack: Vec<(usize, u32)>,
+}
+
+impl LineScanner {
+    pub fn new() -> Self {
+        Self { stack: Vec::new() }
     }
-    for i in 1..map.len() - 1 {
-        for j in 1..map[i].len() - 1 {
-            if is_visible(map, i, j) {
-                visibility_map[i][j] = 1;
-            }
+
+    // returns the viewing distance back along the line from the tree at
+    // position `k` and whether it is visible from the start of the line
+    pub fn visit(&mut self, k: usize, height: u32) -> (usize, bool) {
+        while self.stack.last().is_some_and(|&(_, h)| h < height) {
+            self.stack.pop();
         }
-    }
-    for i in 0..map.len() {
-        for j in 0..map[i].len() {
-            if i == 0 || i == map.len() - 1 || j == 0 || j == map[i].len() - 1 {
-                visibility_map[i][j] = 1;
-            }
+        let view = match self.stack.last() {
+            Some(&(blocking, _)) => (k - blocking, false),
+            None => (k, true),
+        };
+        // trees of the same height are hidden behind the current one
+        if self.stack.last().is_some_and(|&(_, h)| h == height) {
+            self.stack.pop();
         }
+        self.stack.push((k, height));
+        view
     }
-    visibility_map
 }
 
-fn is_visible(map: &Vec<Vec<u32>>, i: usize, j: usize) -> bool {
-    let height = map[i][j];
-    let mut visible_from_left = true;
-    for k in 0..j {
-        if map[i][k] >= height {
-            visible_from_left = false;
-            break;
+// looks along every row and column in both directions once; each tree is
+// pushed onto and popped from a stack at most once per direction, hence the
+// total effort is linear in the number of trees
+fn scan_forest(map: &[Vec<u32>]) -> (Vec<Vec<u32>>, Vec<Vec<u64>>) {
+    let rows = map.len();
+    let cols = map.first().map_or(0, |r| r.len());
+    assert!(
+        map.iter().all(|r| r.len() == cols),
+        "All rows should have the same length"
+    );
+    let mut visibility_map = vec![vec![0; cols]; rows];
+    let mut scenic_map = vec![vec![1; cols]; rows];
+    let mut record = |i: usize, j: usize, (distance, visible): (usize, bool)| {
+        if visible {
+            visibility_map[i][j] = 1;
         }
-    }
-    let mut visible_from_right = true;
-    for k in j + 1..map[i].len() {
-        if map[i][k] >= height {
-            visible_from_right = false;
-            break;
+        scenic_map[i][j] *= distance as u64;
+    };
+
+    for (i, row) in map.iter().enumerate() {
+        let mut from_left = LineScanner::new();
+        for (j, &height) in row.iter().enumerate() {
+            record(i, j, from_left.visit(j, height));
+        }
+        let mut from_right = LineScanner::new();
+        for (j, &height) in row.iter().enumerate().rev() {
+            record(i, j, from_right.visit(cols - 1 - j, height));
         }
     }
-    let mut visible_from_bottom = true;
-    for l in 0..i {
-        if map[l][j] >= height {
-            visible_from_bottom = false;
-            break;
+    // scan all columns simultaneously to walk the map row by row
+    let mut from_top = (0..cols)
+        .map(|_| LineScanner::new())
+        .collect::<Vec<LineScanner>>();
+    for (i, row) in map.iter().enumerate() {
+        for (j, (&height, scanner)) in row.iter().zip(from_top.iter_mut()).enumerate() {
+            record(i, j, scanner.visit(i, height));
         }
     }
-    let mut visible_from_top = true;
-    for l in i + 1..map.len() {
-        if map[l][j] >= height {
-            visible_from_top = false;
-            break;
+    let mut from_bottom = (0..cols)
+        .map(|_| LineScanner::new())
+        .collect::<Vec<LineScanner>>();
+    for (i, row) in map.iter().enumerate().rev() {
+        for (j, (&height, scanner)) in row.iter().zip(from_bottom.iter_mut()).enumerate() {
+            record(i, j, scanner.visit(rows - 1 - i, height));
         }
     }
-    visible_from_left || visible_from_right || visible_from_bottom || visible_from_top
+    (visibility_map, scenic_map)
 }
 
-fn print(map: &Vec<Vec<u32>>) {
-    for l in map {
-        for e in l {
-            print!("{}", e);
+// row and column offsets, clockwise starting upwards
+const DIRECTIONS: [(i64, i64); 8] = [
+    (-1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+];
+
+// trees seen from `position` when looking in `direction` from `extra_height`
+// above its top, up to and including the first tree that is at least as high
+// as the observer; the number of trees is the viewing distance
+fn look(
+    map: &[Vec<u32>],
+    position: (usize, usize),
+    direction: (i64, i64),
+    extra_height: u32,
+) -> Vec<(usize, usize)> {
+    let observer_height = map[position.0][position.1] + extra_height;
+    let mut trees = Vec::new();
+    let (mut i, mut j) = (position.0 as i64, position.1 as i64);
+    loop {
+        i += direction.0;
+        j += direction.1;
+        if i < 0 || j < 0 || i as usize >= map.len() || j as usize >= map[i as usize].len() {
+            break;
+        }
+        trees.push((i as usize, j as usize));
+        if map[i as usize][j as usize] >= observer_height {
+            break;
         }
-        print!("\n");
     }
+    trees
 }
 
-fn compute_scenic_map(map: &Vec<Vec<u32>>) -> Vec<Vec<u32>> {
-    let mut scenic_map = Vec::new();
+// the trees seen in each of the `DIRECTIONS`
+fn look_around(
+    map: &[Vec<u32>],
+    position: (usize, usize),
+    extra_height: u32,
+) -> Vec<Vec<(usize, usize)>> {
+    DIRECTIONS
+        .iter()
+        .map(|&direction| look(map, position, direction, extra_height))
+        .collect()
+}
+
+// the `k` best positions according to `score`, which receives the viewing
+// distances in each of the `DIRECTIONS`; ties are broken by position
+fn top_treehouses(
+    map: &[Vec<u32>],
+    k: usize,
+    extra_height: u32,
+    score: impl Fn(&[usize]) -> u64,
+) -> Vec<((usize, usize), u64)> {
+    let mut best = BinaryHeap::new();
     for i in 0..map.len() {
-        scenic_map.push(Vec::new());
-        scenic_map[i].resize(map[i].len(), 0);
         for j in 0..map[i].len() {
-            scenic_map[i][j] = compute_scenic_score(map, i, j);
+            let distances = look_around(map, (i, j), extra_height)
+                .iter()
+                .map(|trees| trees.len())
+                .collect::<Vec<usize>>();
+            best.push(Reverse((score(&distances), Reverse((i, j)))));
+            if best.len() > k {
+                best.pop();
+            }
         }
     }
-    scenic_map
+    best.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((score, Reverse(position)))| (position, score))
+        .collect()
 }
 
-fn compute_scenic_score(map: &Vec<Vec<u32>>, row: usize, col: usize) -> u32 {
-    let height = map[row][col];
-    let mut scenic_score_top = 0;
-    for i in (0..row).rev() {
-        scenic_score_top += 1;
-        if map[i][col] >= height {
-            break;
-        }
+// deterministic pseudo-random forest for testing large maps
+fn generate_forest(rows: usize, cols: usize, seed: u64) -> Vec<Vec<u32>> {
+    let mut state = seed;
+    (0..rows)
+        .map(|_| {
+            (0..cols)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    ((state >> 33) % 10) as u32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn best_tree(scenic_map: &[Vec<u64>]) -> Option<(usize, usize)> {
+    (0..scenic_map.len())
+        .flat_map(|i| (0..scenic_map[i].len()).map(move |j| (i, j)))
+        .max_by_key(|&(i, j)| scenic_map[i][j])
+}
+
+fn widen(map: &[Vec<u32>]) -> Vec<Vec<u64>> {
+    map.iter()
+        .map(|r| r.iter().map(|&v| v as u64).collect())
+        .collect()
+}
+
+// anchor colours of a perceptually ordered dark blue to yellow colour map,
+// intermediate values are interpolated linearly
+const COLORMAP: [[u8; 3]; 5] = [
+    [68, 1, 84],
+    [59, 82, 139],
+    [33, 145, 140],
+    [94, 201, 98],
+    [253, 231, 37],
+];
+
+const MARKER_COLOR: [u8; 3] = [255, 0, 0];
+
+fn apply_colormap(intensity: u8) -> [u8; 3] {
+    let position = intensity as usize * (COLORMAP.len() - 1);
+    let idx = (position / 255).min(COLORMAP.len() - 2);
+    let fraction = (position - idx * 255) as f64 / 255.0;
+    let mut color = [0; 3];
+    for (c, (&low, &high)) in color
+        .iter_mut()
+        .zip(COLORMAP[idx].iter().zip(COLORMAP[idx + 1].iter()))
+    {
+        *c = (low as f64 + fraction * (high as f64 - low as f64)).round() as u8;
     }
-    let mut scenic_score_bottom = 0;
-    for i in row + 1..map.len() {
-        scenic_score_bottom += 1;
-        if map[i][col] >= height {
-            break;
+    color
+}
+
+// scales values linearly to 0..=255 and encodes them as binary PGM, or as
+// binary PPM if a colour map is applied or a tree is marked; the marker is a
+// small cross centred on the given row and column
+fn encode_image(values: &[Vec<u64>], colormap: bool, marker: Option<(usize, usize)>) -> Vec<u8> {
+    let rows = values.len();
+    let cols = values.first().map_or(0, |r| r.len());
+    let max = values.iter().flatten().max().copied().unwrap_or(0).max(1);
+    let is_marked = |i: usize, j: usize| {
+        marker.is_some_and(|(mi, mj)| {
+            (i == mi && j.abs_diff(mj) <= 2) || (j == mj && i.abs_diff(mi) <= 2)
+        })
+    };
+    let is_color = colormap || marker.is_some();
+    let mut image = format!(
+        "{}\n{} {}\n255\n",
+        if is_color { "P6" } else { "P5" },
+        cols,
+        rows
+    )
+    .into_bytes();
+    for (i, row) in values.iter().enumerate() {
+        for (j, &value) in row.iter().enumerate() {
+            let intensity = (value as u128 * 255 / max as u128) as u8;
+            if !is_color {
+                image.push(intensity);
+            } else if is_marked(i, j) {
+                image.extend(MARKER_COLOR);
+            } else if colormap {
+                image.extend(apply_colormap(intensity));
+            } else {
+                image.extend([intensity; 3]);
+            }
         }
     }
-    let mut scenic_score_left = 0;
-    for j in (0..col).rev() {
-        scenic_score_left += 1;
-        if map[row][j] >= height {
-            break;
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // straightforward implementations that walk all four directions from
+    // every tree
+    fn is_visible(map: &[Vec<u32>], i: usize, j: usize) -> bool {
+        let height = map[i][j];
+        (0..j).all(|k| map[i][k] < height)
+            || (j + 1..map[i].len()).all(|k| map[i][k] < height)
+            || (0..i).all(|l| map[l][j] < height)
+            || (i + 1..map.len()).all(|l| map[l][j] < height)
+    }
+
+    fn compute_scenic_score(map: &[Vec<u32>], row: usize, col: usize) -> u64 {
+        let height = map[row][col];
+        let view = |trees: Vec<u32>| {
+            let n_blocking = trees.iter().position(|&h| h >= height);
+            n_blocking.map_or(trees.len(), |idx| idx + 1) as u64
+        };
+        view((0..row).rev().map(|i| map[i][col]).collect())
+            * view((row + 1..map.len()).map(|i| map[i][col]).collect())
+            * view((0..col).rev().map(|j| map[row][j]).collect())
+            * view((col + 1..map[row].len()).map(|j| map[row][j]).collect())
+    }
+
+    #[test]
+    fn test_scan_forest() {
+        let map = parse_map("30373\n25512\n65332\n33549\n35390");
+        let (visibility_map, scenic_map) = scan_forest(&map);
+        assert_eq!(visibility_map.iter().flatten().sum::<u32>(), 21);
+        assert_eq!(scenic_map.iter().flatten().max(), Some(&8));
+
+        for (rows, cols) in [(1, 1), (1, 7), (9, 2), (37, 53)] {
+            let map = generate_forest(rows, cols, (rows * cols) as u64);
+            let (visibility_map, scenic_map) = scan_forest(&map);
+            for i in 0..rows {
+                for j in 0..cols {
+                    assert_eq!(visibility_map[i][j] == 1, is_visible(&map, i, j));
+                    assert_eq!(scenic_map[i][j], compute_scenic_score(&map, i, j));
+                }
+            }
         }
     }
-    let mut scenic_score_right = 0;
-    for j in col + 1..map[row].len() {
-        scenic_score_right += 1;
-        if map[row][j] >= height {
-            break;
+
+    #[test]
+    fn test_treehouses() {
+        let map = parse_map("30373\n25512\n65332\n33549\n35390");
+        let (_, scenic_map) = scan_forest(&map);
+        let axis_aligned =
+            |distances: &[usize]| distances.iter().step_by(2).map(|&d| d as u64).product();
+        assert_eq!(top_treehouses(&map, 1, 0, axis_aligned), vec![((3, 2), 8)]);
+        for (i, row) in scenic_map.iter().enumerate() {
+            for (j, &scenic_score) in row.iter().enumerate() {
+                let distances = look_around(&map, (i, j), 0)
+                    .iter()
+                    .map(|trees| trees.len())
+                    .collect::<Vec<usize>>();
+                assert_eq!(axis_aligned(&distances), scenic_score);
+            }
         }
+
+        assert_eq!(look(&map, (3, 2), (-1, -1), 0), vec![(2, 1)]);
+        assert_eq!(look(&map, (3, 2), (-1, -1), 1), vec![(2, 1), (1, 0)]);
+        assert_eq!(look(&map, (1, 1), (0, 1), 5), vec![(1, 2), (1, 3), (1, 4)]);
+        let total = |distances: &[usize]| distances.iter().sum::<usize>() as u64;
+        assert_eq!(
+            top_treehouses(&map, 2, 0, total),
+            vec![((0, 3), 12), ((2, 0), 12)]
+        );
+    }
+
+    #[test]
+    fn test_encode_image() {
+        let values = vec![vec![0, 1, 2], vec![4, 3, 0]];
+        assert_eq!(
+            encode_image(&values, false, None),
+            b"P5\n3 2\n255\n\x00\x3f\x7f\xff\xbf\x00".to_vec()
+        );
+        let image = encode_image(&values, true, None);
+        let (header, pixels) = image.split_at(11);
+        assert_eq!(header, b"P6\n3 2\n255\n");
+        assert_eq!(&pixels[..3], &COLORMAP[0]);
+        assert_eq!(&pixels[9..12], &COLORMAP[4]);
+        let image = encode_image(&values, false, Some((1, 0)));
+        let pixels = &image[11..];
+        assert_eq!(&pixels[..3], &MARKER_COLOR);
+        assert_eq!(&pixels[3..6], &[63; 3]);
+        assert_eq!(&pixels[15..18], &MARKER_COLOR);
     }
-    scenic_score_top * scenic_score_bottom * scenic_score_left * scenic_score_right
 }
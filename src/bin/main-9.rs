@@ -1,81 +1,433 @@
-use std::{collections::HashSet, fs};
+use std::{collections::HashSet, env, fmt, fs};
+
+// the first coordinate points up, the second right and the third, only used
+// by three-dimensional ropes, forward
+type Position<const D: usize> = [i64; D];
+
+#[derive(Debug, PartialEq)]
+enum MotionError {
+    Malformed,
+    UnknownDirection(String),
+    InvalidCount(String),
+}
+
+#[derive(Debug)]
+struct ParseError {
+    line: usize,
+    kind: MotionError,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            MotionError::Malformed => write!(f, "expected a direction and a count"),
+            MotionError::UnknownDirection(direction) => {
+                write!(f, "unknown direction {}", direction)
+            }
+            MotionError::InvalidCount(count) => write!(f, "invalid count {}", count),
+        }
+    }
+}
 
 fn main() {
     let file_name = "inputs/9.txt";
     let content = fs::read_to_string(file_name).expect("Should be able to read file");
-    let visited_positions = compute_visited_positions(&content, 10);
-    let tail_positions = visited_positions
-        .iter()
-        .map(|v| v.last().unwrap().clone())
-        .collect::<Vec<(i64, i64)>>();
-    let unique_tail_positions = tail_positions
-        .into_iter()
-        .collect::<HashSet<(i64, i64)>>()
-        .len();
-    println!(
-        "The tail has visited {} positions at least once.",
-        unique_tail_positions
-    );
-}
-
-fn compute_visited_positions(content: &str, rope_length: usize) -> Vec<Vec<(i64, i64)>> {
-    let mut visited_positions = Vec::new();
-    visited_positions.resize(1, Vec::new());
-    for _ in 0..rope_length {
-        visited_positions[0].push((0, 0));
-    }
-    for l in content.lines() {
-        let (direction, count) = parse_instruction(l);
+    let args = env::args().collect::<Vec<String>>();
+    let result = if args.iter().any(|a| a == "--3d") {
+        report::<3>(&content)
+    } else if args.len() > 2 && (args[1] == "--render" || args[1] == "--animate") {
+        visualize(&content, &args[1..])
+    } else {
+        report::<2>(&content)
+    };
+    if let Err(err) = result {
+        println!("Could not parse motions: {}", err);
+    }
+}
+
+// --render <step> [knots]
+// --animate <file> [knots] [every nth step]
+fn visualize(content: &str, args: &[String]) -> Result<(), ParseError> {
+    let motions = parse_motions::<2>(content)?;
+    let rope_length = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(10);
+    let history = compute_visited_positions(&motions, rope_length);
+    let bounds = history_bounds(&history);
+    if args[0] == "--render" {
+        let step = args[1]
+            .parse::<usize>()
+            .expect("Should be able to parse step");
+        let step = step.min(history.len() - 1);
+        let visited = history[..=step]
+            .iter()
+            .map(|knots| *knots.last().unwrap())
+            .collect::<HashSet<Position<2>>>();
+        print!("{}", render(&history[step], &visited, bounds));
+    } else {
+        let every = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(1);
+        let animation = render_animation(&history, bounds, every);
+        fs::write(&args[1], animation).expect("Should be able to write animation");
+        println!("Wrote {} steps to {}.", history.len() - 1, args[1]);
+    }
+    Ok(())
+}
+
+fn report<const D: usize>(content: &str) -> Result<(), ParseError> {
+    let motions = parse_motions::<D>(content)?;
+    // the first knot behind the head moves exactly like the tail of a rope
+    // with two knots, so both parts can be answered by the same simulation
+    let mut rope = Rope::<D>::new(10, &[1, 9]);
+    for (delta, count) in motions {
         for _ in 0..count {
-            let positions = apply_instruction(direction, visited_positions.last().unwrap());
-            visited_positions.push(positions.clone());
+            rope.step(delta);
         }
     }
-    visited_positions
+    for (knots, knot) in [(2, 1), (10, 9)] {
+        let stats = rope.stats(knot).unwrap();
+        let (min, max) = stats.bounding_box();
+        println!(
+            "The tail of a rope with {} knots has visited {} positions at least once, within {:?} to {:?}.",
+            knots,
+            stats.visited_count(),
+            min,
+            max
+        );
+    }
+    Ok(())
+}
+
+// position statistics of a single knot
+struct KnotStats<const D: usize> {
+    visited: HashSet<Position<D>>,
+    min: Position<D>,
+    max: Position<D>,
+}
+
+impl<const D: usize> KnotStats<D> {
+    pub fn new(position: Position<D>) -> Self {
+        Self {
+            visited: HashSet::from([position]),
+            min: position,
+            max: position,
+        }
+    }
+
+    fn record(&mut self, position: Position<D>) {
+        self.visited.insert(position);
+        for (axis, &coordinate) in position.iter().enumerate() {
+            self.min[axis] = self.min[axis].min(coordinate);
+            self.max[axis] = self.max[axis].max(coordinate);
+        }
+    }
+
+    pub fn visited_count(&self) -> usize {
+        self.visited.len()
+    }
+
+    pub fn bounding_box(&self) -> (Position<D>, Position<D>) {
+        (self.min, self.max)
+    }
+}
+
+// a rope whose knots are updated in place, only the tracked knots keep a
+// record of their positions
+struct Rope<const D: usize> {
+    knots: Vec<Position<D>>,
+    tracked: Vec<(usize, KnotStats<D>)>,
+}
+
+impl<const D: usize> Rope<D> {
+    pub fn new(n_knots: usize, tracked: &[usize]) -> Self {
+        assert!(n_knots > 0, "A rope should have at least one knot");
+        assert!(
+            tracked.iter().all(|&knot| knot < n_knots),
+            "Tracked knots should be part of the rope"
+        );
+        Self {
+            knots: vec![[0; D]; n_knots],
+            tracked: tracked
+                .iter()
+                .map(|&knot| (knot, KnotStats::new([0; D])))
+                .collect(),
+        }
+    }
+
+    pub fn step(&mut self, delta: Position<D>) {
+        step_knots(delta, &mut self.knots);
+        for (knot, stats) in self.tracked.iter_mut() {
+            stats.record(self.knots[*knot]);
+        }
+    }
+
+    pub fn stats(&self, knot: usize) -> Option<&KnotStats<D>> {
+        self.tracked
+            .iter()
+            .find(|(k, _)| *k == knot)
+            .map(|(_, stats)| stats)
+    }
+}
+
+fn parse_motions<const D: usize>(content: &str) -> Result<Vec<(Position<D>, usize)>, ParseError> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(idx, l)| {
+            parse_instruction(l).map_err(|kind| ParseError {
+                line: idx + 1,
+                kind,
+            })
+        })
+        .collect()
 }
 
-fn parse_instruction(l: &str) -> (char, usize) {
+// a direction combines at most one letter per axis, e.g., `UL` moves up and
+// left at the same time; `F` and `B` move along the third axis
+fn parse_instruction<const D: usize>(l: &str) -> Result<(Position<D>, usize), MotionError> {
     let mut l_split = l.split_whitespace();
-    (
-        l_split.next().unwrap().chars().next().unwrap(),
-        l_split.next().unwrap().parse().unwrap(),
-    )
-}
-
-fn apply_instruction(direction: char, positions: &Vec<(i64, i64)>) -> Vec<(i64, i64)> {
-    let mut new_positions = Vec::new();
-    let mut head_position = positions[0];
-    match direction {
-        'L' => head_position.1 -= 1,
-        'R' => head_position.1 += 1,
-        'U' => head_position.0 += 1,
-        'D' => head_position.0 -= 1,
-        _ => panic!("Unknown instruction"),
-    }
-    new_positions.push(head_position);
-    for i in 1..positions.len() {
-        let position = update_follower_position(&new_positions[i - 1], &positions[i]);
-        new_positions.push(position);
-    }
-    new_positions
-}
-
-fn update_follower_position(head_position: &(i64, i64), tail_position: &(i64, i64)) -> (i64, i64) {
-    if (head_position.0 - tail_position.0).abs() <= 1
-        && (head_position.1 - tail_position.1).abs() <= 1
-    {
-        return tail_position.clone();
-    }
-    let mut new_tail_position = tail_position.clone();
-    if head_position.0 < new_tail_position.0 {
-        new_tail_position.0 -= 1;
-    } else if head_position.0 > new_tail_position.0 {
-        new_tail_position.0 += 1;
-    }
-    if head_position.1 < new_tail_position.1 {
-        new_tail_position.1 -= 1;
-    } else if head_position.1 > new_tail_position.1 {
-        new_tail_position.1 += 1;
+    let (direction, count) = match (l_split.next(), l_split.next(), l_split.next()) {
+        (Some(direction), Some(count), None) => (direction, count),
+        _ => return Err(MotionError::Malformed),
+    };
+    let mut delta = [0; D];
+    for c in direction.chars() {
+        let (axis, step) = match c {
+            'U' => (0, 1),
+            'D' => (0, -1),
+            'R' => (1, 1),
+            'L' => (1, -1),
+            'F' => (2, 1),
+            'B' => (2, -1),
+            _ => return Err(MotionError::UnknownDirection(direction.to_string())),
+        };
+        if axis >= D || delta[axis] != 0 {
+            return Err(MotionError::UnknownDirection(direction.to_string()));
+        }
+        delta[axis] = step;
+    }
+    let count = count
+        .parse()
+        .map_err(|_| MotionError::InvalidCount(count.to_string()))?;
+    Ok((delta, count))
+}
+
+// the positions of all knots after every step, starting with the initial
+// state; needs memory proportional to the number of steps, see `Rope` for a
+// simulation that does not
+fn compute_visited_positions<const D: usize>(
+    motions: &[(Position<D>, usize)],
+    rope_length: usize,
+) -> Vec<Vec<Position<D>>> {
+    let mut visited_positions = vec![vec![[0; D]; rope_length]];
+    for &(delta, count) in motions {
+        for _ in 0..count {
+            let mut positions = visited_positions.last().unwrap().clone();
+            step_knots(delta, &mut positions);
+            visited_positions.push(positions);
+        }
+    }
+    visited_positions
+}
+
+// the smallest box containing the origin and every knot at every step
+fn history_bounds(history: &[Vec<Position<2>>]) -> (Position<2>, Position<2>) {
+    let mut stats = KnotStats::new([0, 0]);
+    for &position in history.iter().flatten() {
+        stats.record(position);
+    }
+    stats.bounding_box()
+}
+
+// draws the rope like the puzzle's diagrams: `H` for the head, `T` for the
+// tail of a two-knot rope and the index for any other knot, `s` for the origin
+// and `#` for the positions visited by the tail; knots closer to the head are
+// drawn on top
+fn render(
+    knots: &[Position<2>],
+    visited: &HashSet<Position<2>>,
+    bounds: (Position<2>, Position<2>),
+) -> String {
+    let (min, max) = bounds;
+    let mut grid = Vec::new();
+    for i in (min[0]..=max[0]).rev() {
+        let mut row = Vec::new();
+        for j in min[1]..=max[1] {
+            let cell = if [i, j] == [0, 0] {
+                's'
+            } else if visited.contains(&[i, j]) {
+                '#'
+            } else {
+                '.'
+            };
+            row.push(cell);
+        }
+        grid.push(row);
+    }
+    for (idx, knot) in knots.iter().enumerate().rev() {
+        let (i, j) = ((max[0] - knot[0]) as usize, (knot[1] - min[1]) as usize);
+        let Some(cell) = grid.get_mut(i).and_then(|row| row.get_mut(j)) else {
+            continue;
+        };
+        *cell = match idx {
+            0 => 'H',
+            1 if knots.len() == 2 => 'T',
+            _ => char::from_digit(idx as u32 % 36, 36).unwrap(),
+        };
+    }
+    grid.into_iter()
+        .map(|row| row.into_iter().collect::<String>() + "\n")
+        .collect()
+}
+
+// every `every`th step of the history as a sequence of frames, the last step
+// is always included
+fn render_animation(
+    history: &[Vec<Position<2>>],
+    bounds: (Position<2>, Position<2>),
+    every: usize,
+) -> String {
+    let every = every.max(1);
+    let mut visited = HashSet::new();
+    let mut animation = String::new();
+    for (step, knots) in history.iter().enumerate() {
+        visited.insert(*knots.last().unwrap());
+        if step % every == 0 || step == history.len() - 1 {
+            animation += &format!("== Step {} ==\n", step);
+            animation += &render(knots, &visited, bounds);
+            animation += "\n";
+        }
+    }
+    animation
+}
+
+fn step_knots<const D: usize>(delta: Position<D>, knots: &mut [Position<D>]) {
+    for axis in 0..D {
+        knots[0][axis] += delta[axis];
+    }
+    for i in 1..knots.len() {
+        let position = update_follower_position(&knots[i - 1], &knots[i]);
+        // knots further down the rope can only move if this one did
+        if position == knots[i] {
+            break;
+        }
+        knots[i] = position;
+    }
+}
+
+fn update_follower_position<const D: usize>(
+    head_position: &Position<D>,
+    tail_position: &Position<D>,
+) -> Position<D> {
+    if (0..D).all(|axis| (head_position[axis] - tail_position[axis]).abs() <= 1) {
+        return *tail_position;
+    }
+    let mut new_tail_position = *tail_position;
+    for axis in 0..D {
+        new_tail_position[axis] += (head_position[axis] - tail_position[axis]).signum();
     }
     new_tail_position
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulate<const D: usize>(content: &str, n_knots: usize, tracked: &[usize]) -> Rope<D> {
+        let mut rope = Rope::new(n_knots, tracked);
+        for (delta, count) in parse_motions(content).unwrap() {
+            for _ in 0..count {
+                rope.step(delta);
+            }
+        }
+        rope
+    }
+
+    #[test]
+    fn test_rope() {
+        let content = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2";
+        let rope = simulate::<2>(content, 10, &[0, 1, 9]);
+        assert_eq!(rope.stats(1).unwrap().visited_count(), 13);
+        assert_eq!(rope.stats(9).unwrap().visited_count(), 1);
+        assert_eq!(rope.stats(0).unwrap().bounding_box(), ([0, 0], [4, 5]));
+        assert!(rope.stats(2).is_none());
+        assert_eq!(
+            rope.stats(1).unwrap().visited,
+            simulate::<2>(content, 2, &[1]).stats(1).unwrap().visited
+        );
+        // moving in the plane of a three-dimensional rope changes nothing
+        assert_eq!(
+            simulate::<3>(content, 2, &[1])
+                .stats(1)
+                .unwrap()
+                .visited_count(),
+            13
+        );
+
+        let content = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20";
+        let rope = simulate::<2>(content, 10, &[9]);
+        assert_eq!(rope.stats(9).unwrap().visited_count(), 36);
+        assert_eq!(rope.stats(9).unwrap().bounding_box(), ([-5, -11], [6, 10]));
+    }
+
+    #[test]
+    fn test_render() {
+        let motions = parse_motions("R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2").unwrap();
+        let history = compute_visited_positions(&motions, 2);
+        assert_eq!(history.len(), 25);
+        let bounds = history_bounds(&history);
+        assert_eq!(bounds, ([0, 0], [4, 5]));
+        let visited = history
+            .iter()
+            .map(|knots| knots[1])
+            .collect::<HashSet<Position<2>>>();
+        assert_eq!(
+            render(&history[24], &visited, bounds),
+            "..##..\n...##.\n.TH##.\n....#.\ns###..\n"
+        );
+        assert_eq!(
+            render(&history[0], &HashSet::new(), bounds),
+            "......\n......\n......\n......\nH.....\n"
+        );
+
+        let history = compute_visited_positions(&motions, 10);
+        assert_eq!(
+            render(&history[8], &HashSet::new(), bounds),
+            "....H.\n....1.\n..432.\n.5....\n6.....\n"
+        );
+        let animation = render_animation(&history, bounds, 10);
+        assert_eq!(animation.matches("== Step").count(), 4);
+        assert!(animation.ends_with("== Step 24 ==\n......\n......\n.1H3..\n.5....\n6.....\n\n"));
+    }
+
+    #[test]
+    fn test_extended_motions() {
+        let rope = simulate::<2>("UR 3\nDL 1", 2, &[0, 1]);
+        assert_eq!(rope.knots, vec![[2, 2], [2, 2]]);
+        assert_eq!(rope.stats(1).unwrap().visited_count(), 3);
+
+        let rope = simulate::<3>("F 2\nUF 1\nLB 2", 3, &[2]);
+        assert_eq!(rope.knots, vec![[1, -2, 1], [1, -1, 1], [1, 0, 1]]);
+        assert_eq!(
+            rope.stats(2).unwrap().bounding_box(),
+            ([0, 0, 0], [1, 0, 1])
+        );
+
+        assert_eq!(parse_instruction::<2>("UL 4"), Ok(([1, -1], 4)));
+        assert_eq!(
+            parse_instruction::<2>("F 1"),
+            Err(MotionError::UnknownDirection("F".to_string()))
+        );
+        assert_eq!(
+            parse_instruction::<3>("UD 1"),
+            Err(MotionError::UnknownDirection("UD".to_string()))
+        );
+        assert_eq!(
+            parse_instruction::<2>("R x"),
+            Err(MotionError::InvalidCount("x".to_string()))
+        );
+        assert_eq!(parse_instruction::<2>("R"), Err(MotionError::Malformed));
+        let err = parse_motions::<2>("R 1\n\nX 2").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.to_string(), "line 3: unknown direction X");
+    }
+}